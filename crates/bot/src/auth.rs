@@ -0,0 +1,134 @@
+//! Requests to the authentication backend, done with the same helpers as the client.
+//!
+//! Token requests don't block: they run on ehttp's threads and are received through a channel,
+//! so that connected bots keep ticking while new ones are authenticating.
+//!
+//! Bots log in to the accounts they created in previous runs, stored in a [`BotAccounts`] file,
+//! so that the server's account store doesn't grow with each run.
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+use bevy::log::warn;
+use bevy::tasks::block_on;
+use serde::{Deserialize, Serialize};
+use shared::auth::{
+    AuthPayload, NewClientPayload, ServerInfo, TokenResponse, generate_client_secret,
+};
+use shared::client::auth::{
+    AuthClientError, connect_client_request, create_client_request, decode_response,
+    fetch_server_info,
+};
+
+/// Credentials of the account of a bot.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BotAccount {
+    pub client_id: u64,
+    pub secret: String,
+}
+
+/// Accounts of the bots by bot index, persisted in a JSON file between runs.
+///
+/// Delete the file to start over with new accounts, the previous ones are left on the server.
+pub struct BotAccounts {
+    path: PathBuf,
+    accounts: BTreeMap<usize, BotAccount>,
+}
+
+impl BotAccounts {
+    /// Read the accounts file, a missing file has no accounts.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let accounts = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            accounts,
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&BotAccount> {
+        self.accounts.get(&index)
+    }
+
+    /// Remember the account of bot `index`, and save the file if it changed.
+    pub fn insert(&mut self, index: usize, account: BotAccount) -> io::Result<()> {
+        if self.accounts.get(&index) == Some(&account) {
+            return Ok(());
+        }
+        self.accounts.insert(index, account);
+        let json = serde_json::to_vec_pretty(&self.accounts)?;
+        // write then rename, so that a crash doesn't lose the accounts of all bots
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+/// Get the game server address and certificate digest, blocking.
+pub fn fetch_server_info_blocking(auth_url: &str) -> Result<ServerInfo, AuthClientError> {
+    block_on(fetch_server_info(auth_url))
+}
+
+/// The connect token of a bot, along with the account it is for.
+pub type TokenResult = Result<(TokenResponse, BotAccount), AuthClientError>;
+
+/// Get a connect token for bot `index`, the result is sent on `results`.
+///
+/// Logs in to `account` if the bot has one, and creates a new account if it doesn't or if the
+/// server lost it.
+pub fn request_token(
+    auth_url: &str,
+    index: usize,
+    account: Option<BotAccount>,
+    results: Sender<(usize, TokenResult)>,
+) {
+    let Some(account) = account else {
+        create_account(auth_url, index, results);
+        return;
+    };
+    let payload = AuthPayload {
+        client_id: account.client_id,
+        client_secret: account.secret.clone(),
+    };
+    let request = connect_client_request(auth_url, &payload);
+    let url = request.url.clone();
+    let auth_url = auth_url.to_string();
+    ehttp::fetch(request, move |result| {
+        match decode_response(url, result) {
+            Err(e) if e.is_account_lost() => {
+                warn!("Bot {index} lost client {} ({e})", account.client_id);
+                create_account(&auth_url, index, results);
+            }
+            result => {
+                // the receiver is gone when the bots are shutting down
+                let _ = results.send((index, result.map(|token| (token, account))));
+            }
+        }
+    });
+}
+
+fn create_account(auth_url: &str, index: usize, results: Sender<(usize, TokenResult)>) {
+    let secret = generate_client_secret();
+    let payload = NewClientPayload {
+        client_secret: secret.clone(),
+        display_name: Some(format!("Bot-{index}")),
+    };
+    let request = create_client_request(auth_url, &payload);
+    let url = request.url.clone();
+    ehttp::fetch(request, move |result| {
+        let result = decode_response(url, result).map(|token: TokenResponse| {
+            let account = BotAccount {
+                client_id: token.client_id,
+                secret,
+            };
+            (token, account)
+        });
+        let _ = results.send((index, result));
+    });
+}
@@ -0,0 +1,203 @@
+//! A bot is a headless client app: it connects with a connect token from the auth backend,
+//! joins the game and moves its player by writing its `ActionState` instead of reading a keyboard.
+use core::net::{Ipv4Addr, SocketAddr};
+use core::time::Duration;
+
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use clap::ValueEnum;
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::{ActionState, InputMap};
+use lightyear::netcode::client_plugin::NetcodeConfig;
+use lightyear::netcode::{ConnectToken, NetcodeClient};
+use lightyear::prelude::client::*;
+use lightyear::prelude::*;
+use rand::Rng;
+use shared::SharedPlugin;
+use shared::protocol::{PlayerActions, PlayerId};
+use shared::settings::ASSETS_PATH;
+
+/// How the bots move.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum Behaviour {
+    /// Random directions for random durations, sometimes firing.
+    #[default]
+    Random,
+    /// Up, right, down and left for a second each.
+    Square,
+}
+
+const MOVEMENTS: [&[PlayerActions]; 9] = [
+    &[],
+    &[PlayerActions::Up],
+    &[PlayerActions::Up, PlayerActions::Right],
+    &[PlayerActions::Right],
+    &[PlayerActions::Down, PlayerActions::Right],
+    &[PlayerActions::Down],
+    &[PlayerActions::Down, PlayerActions::Left],
+    &[PlayerActions::Left],
+    &[PlayerActions::Up, PlayerActions::Left],
+];
+const SQUARE: [PlayerActions; 4] = [
+    PlayerActions::Up,
+    PlayerActions::Right,
+    PlayerActions::Down,
+    PlayerActions::Left,
+];
+const ACTIONS: [PlayerActions; 5] = [
+    PlayerActions::Up,
+    PlayerActions::Down,
+    PlayerActions::Left,
+    PlayerActions::Right,
+    PlayerActions::Fire,
+];
+/// Chance of firing when a random bot picks a new direction
+const FIRE_PROBABILITY: f64 = 0.2;
+
+pub struct BotPlugin {
+    pub behaviour: Behaviour,
+}
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BotBehaviour(self.behaviour));
+        // joins the game once the announced map is loaded, like the client
+        app.add_plugins(shared::client::map::plugin);
+        app.add_observer(handle_predicted_spawn);
+        app.add_systems(
+            PreUpdate,
+            drive_inputs.in_set(InputManagerSystem::ManualControl),
+        );
+    }
+}
+
+#[derive(Resource)]
+struct BotBehaviour(Behaviour);
+
+/// Actions the bot presses until its timer finishes.
+#[derive(Component)]
+struct BotInputs {
+    pressed: Vec<PlayerActions>,
+    timer: Timer,
+    step: usize,
+}
+
+impl Default for BotInputs {
+    fn default() -> Self {
+        Self {
+            pressed: Vec::new(),
+            // pick the first movement right away
+            timer: Timer::new(Duration::ZERO, TimerMode::Once),
+            step: 0,
+        }
+    }
+}
+
+impl BotInputs {
+    fn next(&mut self, behaviour: Behaviour) {
+        let mut rng = rand::rng();
+        let duration = match behaviour {
+            Behaviour::Random => {
+                self.pressed = MOVEMENTS[rng.random_range(0..MOVEMENTS.len())].to_vec();
+                if rng.random_bool(FIRE_PROBABILITY) {
+                    self.pressed.push(PlayerActions::Fire);
+                }
+                Duration::from_secs_f32(rng.random_range(0.5..2.0))
+            }
+            Behaviour::Square => {
+                self.pressed = vec![SQUARE[self.step % SQUARE.len()]];
+                Duration::from_secs(1)
+            }
+        };
+        self.step += 1;
+        self.timer = Timer::new(duration, TimerMode::Once);
+    }
+}
+
+/// The controlled player gets an empty `InputMap`: inputs come from [`BotInputs`].
+///
+/// Physics and movement prediction are handled by the shared plugin, like for the client.
+fn handle_predicted_spawn(
+    trigger: On<Add, (PlayerId, Predicted)>,
+    predicted: Query<(), (With<Predicted>, With<PlayerId>, With<Controlled>)>,
+    mut commands: Commands,
+) {
+    if predicted.contains(trigger.entity) {
+        commands
+            .entity(trigger.entity)
+            .insert((InputMap::<PlayerActions>::default(), BotInputs::default()));
+    }
+}
+
+/// Runs after leafwing updated the action states from the (empty) input maps, so it isn't overwritten.
+fn drive_inputs(
+    time: Res<Time>,
+    behaviour: Res<BotBehaviour>,
+    mut bots: Query<(&mut BotInputs, &mut ActionState<PlayerActions>)>,
+) {
+    for (mut inputs, mut action_state) in bots.iter_mut() {
+        if inputs.timer.tick(time.delta()).is_finished() {
+            inputs.next(behaviour.0);
+        }
+        for action in ACTIONS {
+            if inputs.pressed.contains(&action) {
+                action_state.press(&action);
+            } else {
+                action_state.release(&action);
+            }
+        }
+    }
+}
+
+/// Everything a bot needs to connect to the game server.
+pub struct BotConnection {
+    pub token: ConnectToken,
+    pub server_addr: SocketAddr,
+    pub certificate_digest: String,
+}
+
+/// Build the app of a bot and start connecting it. Returns the app and its `Client` entity.
+///
+/// The app is not run: the caller updates it, so that all bots share a thread.
+pub fn new_bot_app(
+    connection: BotConnection,
+    behaviour: Behaviour,
+    tick_duration: Duration,
+) -> Result<(App, Entity), BevyError> {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin {
+            file_path: ASSETS_PATH.to_string(),
+            ..default()
+        },
+        StatesPlugin,
+    ));
+    app.add_plugins((ClientPlugins { tick_duration }, SharedPlugin));
+    app.add_plugins(BotPlugin { behaviour });
+    app.finish();
+    app.cleanup();
+
+    let client = app
+        .world_mut()
+        .spawn((
+            Client::default(),
+            Link::new(None),
+            // any free port
+            LocalAddr(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)),
+            PeerAddr(connection.server_addr),
+            WebTransportClientIo {
+                certificate_digest: connection.certificate_digest,
+            },
+            ReplicationReceiver::default(),
+            PredictionManager::default(),
+            NetcodeClient::new(
+                Authentication::Token(connection.token),
+                NetcodeConfig::default(),
+            )?,
+            Name::from("Bot"),
+        ))
+        .id();
+    app.world_mut().trigger(Connect { entity: client });
+    Ok((app, client))
+}
@@ -0,0 +1,189 @@
+//! Headless bots for load testing: each bot authenticates against the auth backend, connects to the
+//! game server and moves randomly (or along a square), like a player would.
+//!
+//! All bots run in this process, as separate bevy apps updated one after the other on the main
+//! thread: no window nor GPU is needed.
+//!
+//! The auth backend rate limits account creation per IP, raise `[rate_limit]` in the server config
+//! to spawn many bots quickly. Rate limited bots wait and retry.
+//!
+//! Accounts are created on the first run only: they are kept in `--accounts-path` and reused after.
+//!
+//! Run with `cargo run --release --bin bot -- --count 50`
+
+mod auth;
+mod bot;
+
+use core::cmp::Reverse;
+use core::net::SocketAddr;
+use core::time::Duration;
+use std::collections::BinaryHeap;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use bevy::log::tracing_subscriber::{self, EnvFilter};
+use bevy::prelude::*;
+use clap::Parser;
+use lightyear::netcode::ConnectToken;
+use lightyear::prelude::Connected;
+use shared::auth::AUTH_BACKEND_URL;
+use shared::settings::FIXED_TIMESTEP_HZ;
+
+use crate::auth::BotAccounts;
+use crate::bot::{Behaviour, BotConnection, new_bot_app};
+
+/// Command line flags, each of them can also be set through an environment variable.
+#[derive(Parser, Debug)]
+#[command(about = "Headless bots, to load test the game server")]
+struct Args {
+    /// Number of bots to run
+    #[arg(long, short = 'n', env = "BOT_COUNT", default_value_t = 10)]
+    count: usize,
+    /// Milliseconds between two bots requesting a token
+    #[arg(long, env = "BOT_SPAWN_INTERVAL_MS", default_value_t = 100)]
+    spawn_interval_ms: u64,
+    #[arg(long, env = "BOT_BEHAVIOUR", value_enum, default_value_t)]
+    behaviour: Behaviour,
+    /// Base url of the authentication backend
+    #[arg(long, env = "BOT_AUTH_URL", default_value = AUTH_BACKEND_URL)]
+    auth_url: String,
+    /// Address of the game server [default: advertised by the auth backend]
+    #[arg(long, env = "BOT_SERVER_ADDR")]
+    server_addr: Option<SocketAddr>,
+    /// Hex encoded sha256 digest of the game server certificate [default: advertised by the auth backend]
+    #[arg(long, env = "BOT_CERT_DIGEST")]
+    cert_digest: Option<String>,
+    /// File where the accounts of the bots are kept, so that each run reuses them
+    #[arg(long, env = "BOT_ACCOUNTS_PATH", default_value = "bot_accounts.json")]
+    accounts_path: PathBuf,
+}
+
+/// A running bot
+struct Bot {
+    app: App,
+    client: Entity,
+}
+
+/// Interval between two logs of the number of connected bots
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+const LOG_FILTER: &str = "info,bevy_ecs=warn,bevy_time=warn";
+
+fn main() {
+    let args = Args::parse();
+    // set up once for all the bots, instead of a `LogPlugin` per app
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or(EnvFilter::new(LOG_FILTER)))
+        .init();
+    let mut accounts = BotAccounts::load(&args.accounts_path).unwrap_or_else(|e| {
+        eprintln!(
+            "Failed to read bot accounts from {}: {e}",
+            args.accounts_path.display()
+        );
+        std::process::exit(1);
+    });
+    let server_info = auth::fetch_server_info_blocking(&args.auth_url).unwrap_or_else(|e| {
+        eprintln!("Failed to get server info from {}: {e}", args.auth_url);
+        std::process::exit(1);
+    });
+    let server_addr = args.server_addr.unwrap_or(server_info.game_server_addr);
+    let certificate_digest = args
+        .cert_digest
+        .unwrap_or(server_info.certificate_digest)
+        .trim()
+        .replace(':', "");
+    let tick_duration = Duration::from_secs_f64(1.0 / FIXED_TIMESTEP_HZ);
+    let spawn_interval = Duration::from_millis(args.spawn_interval_ms);
+
+    let (token_sender, token_receiver) = mpsc::channel();
+    // bots waiting to request a token, by request time
+    let start = Instant::now();
+    let mut pending: BinaryHeap<Reverse<(Instant, usize)>> = (0..args.count)
+        .map(|index| Reverse((start + spawn_interval * index as u32, index)))
+        .collect();
+    let mut bots: Vec<Bot> = Vec::with_capacity(args.count);
+    let mut failed = 0;
+    let mut last_status = Instant::now();
+
+    loop {
+        let frame_start = Instant::now();
+
+        while let Some(Reverse((at, index))) = pending.peek().copied()
+            && at <= frame_start
+        {
+            pending.pop();
+            let account = accounts.get(index).cloned();
+            auth::request_token(&args.auth_url, index, account, token_sender.clone());
+        }
+        for (index, result) in token_receiver.try_iter() {
+            let token_response = match result {
+                Ok((token_response, account)) => {
+                    if let Err(e) = accounts.insert(index, account) {
+                        error!("Could not save the account of bot {index}: {e}");
+                    }
+                    token_response
+                }
+                Err(e) => match e.retry_after() {
+                    Some(retry_after) => {
+                        info!("Bot {index} rate limited, retrying in {retry_after:?}");
+                        pending.push(Reverse((frame_start + retry_after, index)));
+                        continue;
+                    }
+                    None => {
+                        error!("Bot {index} could not get a token: {e}");
+                        failed += 1;
+                        continue;
+                    }
+                },
+            };
+            let token = match ConnectToken::try_from_bytes(&token_response.token) {
+                Ok(token) => token,
+                Err(e) => {
+                    error!("Bot {index} received an invalid token: {e:?}");
+                    failed += 1;
+                    continue;
+                }
+            };
+            let connection = BotConnection {
+                token,
+                server_addr,
+                certificate_digest: certificate_digest.clone(),
+            };
+            match new_bot_app(connection, args.behaviour, tick_duration) {
+                Ok((app, client)) => {
+                    info!(
+                        "Bot {index} connecting as client {}",
+                        token_response.client_id
+                    );
+                    bots.push(Bot { app, client });
+                }
+                Err(e) => {
+                    error!("Bot {index} could not start: {e}");
+                    failed += 1;
+                }
+            }
+        }
+
+        for bot in &mut bots {
+            bot.app.update();
+        }
+
+        if last_status.elapsed() >= STATUS_INTERVAL {
+            last_status = Instant::now();
+            let connected = bots
+                .iter()
+                .filter(|bot| bot.app.world().get::<Connected>(bot.client).is_some())
+                .count();
+            info!(
+                "{connected}/{} bots connected, {} waiting for a token, {failed} failed",
+                args.count,
+                args.count - bots.len() - failed
+            );
+        }
+
+        // bots run their fixed updates at the tick rate, updating them faster would only burn CPU
+        if let Some(remaining) = tick_duration.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+}
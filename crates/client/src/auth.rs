@@ -5,30 +5,33 @@
 //! - applying inputs to the locally predicted player (for prediction to work, inputs have to be applied to both the
 //! predicted entity and the server entity)
 use core::net::SocketAddr;
-use serde_json::json;
-use shared::auth::{AuthPayload, NewClientPayload, TokenResponse};
+use shared::auth::{
+    AuthPayload, MIN_CLIENT_SECRET_LEN, NewClientPayload, ServerInfo, TokenResponse,
+    generate_client_secret,
+};
+use shared::client::auth::{
+    AuthClientError, connect_client_request, create_client_request, fetch_json, fetch_server_info,
+};
 use std::pin::pin;
 use std::task::Poll;
 
 use bevy::prelude::*;
+use bevy::tasks::futures_lite::FutureExt;
 use bevy::tasks::{IoTaskPool, Task};
 use lightyear::connection::client::ClientState;
 use lightyear::netcode::ConnectToken;
 use lightyear::prelude::client::*;
 use lightyear::prelude::*;
 
-use crate::AuthPrefs;
+use crate::client_renderer::UpdateStatusMessage;
+use crate::settings::{ClientArgs, ConnectionPrefs, ConnectionTarget, DigestSource};
+use crate::{AuthAccount, AuthPrefs};
 
-pub struct AuthClientPlugin {
-    pub auth_backend_address: SocketAddr,
-}
+pub struct AuthClientPlugin;
 
 impl Plugin for AuthClientPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(ConnectTokenRequestTask {
-            auth_backend_addr: self.auth_backend_address,
-            task: None,
-        });
+        app.insert_resource(ConnectTokenRequestTask { task: None });
 
         // despawn the existing connect button from the Renderer if it exists
         // (because we want to replace it with one with specific behaviour)
@@ -50,8 +53,16 @@ impl Plugin for AuthClientPlugin {
 /// Holds a handle to an io task that is requesting a `ConnectToken` from the backend
 #[derive(Resource)]
 struct ConnectTokenRequestTask {
-    auth_backend_addr: SocketAddr,
-    task: Option<Task<Option<TokenResponse>>>,
+    task: Option<Task<Result<ConnectInfo, AuthClientError>>>,
+}
+
+/// Everything needed to start a connection to the game server.
+struct ConnectInfo {
+    /// The auth backend the token comes from
+    auth_url: String,
+    token_response: TokenResponse,
+    server_addr: SocketAddr,
+    certificate_digest: String,
 }
 
 /// If we have an io task that is waiting for a `ConnectToken`, we poll the task until completion,
@@ -72,19 +83,30 @@ fn fetch_connect_token(
                     _ => None,
                 }
             }
-            if let Some(mb_token_response) = now_or_never(task) {
-                info!("Received ConnectToken, starting connection!");
-                let Some(token_response) = mb_token_response else {
-                    prefs.last_token = None;
-                    prefs.secret = None;
-                    connect_token_request.task = None;
-                    return Ok(());
+            if let Some(mb_connect_info) = now_or_never(task) {
+                connect_token_request.task = None;
+                // Keep the secret on failure: the account may still exist once the backend is reachable.
+                let connect_info = match mb_connect_info {
+                    Ok(connect_info) => connect_info,
+                    Err(e) => {
+                        error!("Failed to get a ConnectToken: {e}");
+                        commands.trigger(UpdateStatusMessage(e.to_string()));
+                        return Ok(());
+                    }
                 };
+                info!("Received ConnectToken, starting connection!");
                 let client = client.into_inner();
 
-                start_lightyear_connect(commands, client, &token_response)?;
-                prefs.last_token = Some(token_response);
-                connect_token_request.task = None;
+                if let Err(e) = start_lightyear_connect(&mut commands, client, &connect_info) {
+                    error!("Failed to start the connection: {e}");
+                    commands.trigger(UpdateStatusMessage(format!("Connection failed: {e}")));
+                    return Ok(());
+                }
+                prefs
+                    .accounts
+                    .entry(connect_info.auth_url)
+                    .or_default()
+                    .last_token = Some(connect_info.token_response);
             }
         }
     }
@@ -92,16 +114,22 @@ fn fetch_connect_token(
 }
 
 fn start_lightyear_connect(
-    mut commands: Commands<'_, '_>,
+    commands: &mut Commands<'_, '_>,
     client: Entity,
-    token_response: &TokenResponse,
+    connect_info: &ConnectInfo,
 ) -> Result<(), BevyError> {
-    let connect_token = ConnectToken::try_from_bytes(&token_response.token)
-        .expect("Failed to parse token from authentication server");
-    commands.entity(client).insert(NetcodeClient::new(
-        Authentication::Token(connect_token),
-        NetcodeConfig::default(),
-    )?);
+    let connect_token = ConnectToken::try_from_bytes(&connect_info.token_response.token)
+        .map_err(|e| format!("invalid token from the authentication server: {e:?}"))?;
+    commands.entity(client).insert((
+        PeerAddr(connect_info.server_addr),
+        WebTransportClientIo {
+            certificate_digest: connect_info.certificate_digest.clone(),
+        },
+        NetcodeClient::new(
+            Authentication::Token(connect_token),
+            NetcodeConfig::default(),
+        )?,
+    ));
     commands.trigger(Connect { entity: client });
     Ok(())
 }
@@ -110,64 +138,83 @@ fn start_lightyear_connect(
 #[derive(Component)]
 pub struct ClientIdText;
 
+/// Get the digest of the game server certificate, so that the WebTransport connection can trust it.
+async fn fetch_certificate_digest(
+    source: DigestSource,
+    server_info: Option<&ServerInfo>,
+) -> Result<String, AuthClientError> {
+    let digest = match source {
+        DigestSource::Known(digest) => digest,
+        DigestSource::Url(url) => {
+            let response = ehttp::fetch_async(ehttp::Request::get(&url))
+                .await
+                .map_err(|error| AuthClientError::Unreachable {
+                    url: url.clone(),
+                    error,
+                })?;
+            if !response.ok {
+                return Err(AuthClientError::Rejected {
+                    url,
+                    status: response.status,
+                    body: None,
+                });
+            }
+            let Some(text) = response.text() else {
+                return Err(AuthClientError::InvalidResponse {
+                    url,
+                    error: "the certificate digest is not text".to_string(),
+                });
+            };
+            text.to_string()
+        }
+        DigestSource::ServerInfo => server_info
+            .ok_or(AuthClientError::MissingServerInfo)?
+            .certificate_digest
+            .clone(),
+    };
+    Ok(digest.trim().replace(':', ""))
+}
+
+/// Resolve the game server address and certificate digest.
+async fn fetch_connection_details(
+    target: ConnectionTarget,
+) -> Result<(SocketAddr, String), AuthClientError> {
+    let server_info = if target.needs_server_info() {
+        Some(fetch_server_info(&target.auth_url).await?)
+    } else {
+        None
+    };
+    let server_addr = target
+        .server_addr
+        .or(server_info.as_ref().map(|info| info.game_server_addr))
+        .ok_or(AuthClientError::MissingServerInfo)?;
+    let certificate_digest =
+        fetch_certificate_digest(target.certificate_digest, server_info.as_ref()).await?;
+    Ok((server_addr, certificate_digest))
+}
+
 /// Get a ConnectToken via a TCP connection to the authentication server
 async fn create_client_from_auth_backend(
-    auth_backend_address: SocketAddr,
+    auth_url: String,
     secret: String,
-) -> Option<TokenResponse> {
-    let url = format!("http://{}/create_client", auth_backend_address);
+) -> Result<TokenResponse, AuthClientError> {
     let payload = NewClientPayload {
         client_secret: secret,
+        display_name: None,
     };
-    let mut req = ehttp::Request::post(url, serde_json::to_vec(&payload).unwrap());
-    req.headers
-        .insert("Content-Type", "application/json; charset=utf8");
-    req.headers.insert("Accept", "application/json");
-    let response = ehttp::fetch_async(req).await.unwrap_or_else(|_| {
-        panic!(
-            "Failed to connect to authentication server on {:?}",
-            auth_backend_address
-        )
-    });
-
-    info!(
-        "Received response: {:?}. Token len: {:?}",
-        response,
-        response.bytes.len()
-    );
-
-    serde_json::from_slice::<TokenResponse>(&response.bytes).ok()
+    fetch_json(create_client_request(&auth_url, &payload)).await
 }
 
 async fn connect_existing_client_from_auth_backend(
-    auth_backend_address: SocketAddr,
+    auth_url: String,
     client_id: u64,
     secret: String,
-) -> Option<TokenResponse> {
-    let url = format!("http://{}/connect_client", auth_backend_address);
+) -> Result<TokenResponse, AuthClientError> {
     let payload = AuthPayload {
         client_id,
         client_secret: secret,
     };
-    let mut req = ehttp::Request::post(url, serde_json::to_vec(&payload).unwrap());
-    req.headers
-        .insert("Content-Type", "application/json; charset=utf8");
-    req.headers.insert("Accept", "application/json");
-    let response = ehttp::fetch_async(req).await.ok().or_else(|| {
-        error!(
-            "Failed to connect to authentication server on {:?}",
-            auth_backend_address
-        );
-        None
-    })?;
-
-    info!(
-        "Received response: {:?}. Token len: {:?}",
-        response,
-        response.bytes.len()
-    );
-    let token_response = serde_json::from_slice::<TokenResponse>(&response.bytes).ok();
-    token_response
+    fetch_json(connect_client_request(&auth_url, &payload)).await
 }
 
 /// Remove all entities when the client disconnect
@@ -224,44 +271,73 @@ fn connect_system(
     mut task_state: ResMut<ConnectTokenRequestTask>,
     client: Single<(Entity, &Client)>,
     mut prefs: ResMut<AuthPrefs>,
+    args: Res<ClientArgs>,
+    connection_prefs: Res<ConnectionPrefs>,
 ) {
     let (client_entity, client) = client.into_inner();
     match client.state {
         ClientState::Disconnected => {
             // Check if we have a token saved, if we do, use it, otherwise create a new one.
             info!("Starting task to get ConnectToken");
-            let auth_backend_addr = task_state.auth_backend_addr;
+            let target = ConnectionTarget::resolve(&args, &connection_prefs);
+            info!("Connecting through auth backend {}", target.auth_url);
+            let auth_url = target.auth_url.clone();
+            let account = prefs.accounts.entry(auth_url.clone()).or_default();
+
+            // Secrets from older versions may be empty or too short, the backend would refuse them.
+            let has_valid_secret = account
+                .secret
+                .as_ref()
+                .is_some_and(|secret| secret.len() >= MIN_CLIENT_SECRET_LEN);
+            if !has_valid_secret {
+                info!("Generating a new client secret.");
+                account.secret = Some(generate_client_secret());
+                account.last_token = None;
+            }
 
-            let task = if let AuthPrefs {
+            let token_task = if let AuthAccount {
                 secret: Some(secret),
                 last_token: Some(last_token),
-            } = prefs.as_ref()
+            } = &*account
             {
                 let secret = secret.clone();
-                let last_token = last_token.clone();
+                let client_id = last_token.client_id;
                 info!("Get a token for a already created client.");
-                IoTaskPool::get().spawn_local(async move {
-                    if let Some(response) = connect_existing_client_from_auth_backend(
-                        auth_backend_addr,
-                        last_token.client_id,
+                async move {
+                    let result = connect_existing_client_from_auth_backend(
+                        auth_url.clone(),
+                        client_id,
                         // Use the same secret as before.
                         secret.clone(),
                     )
-                    .await
-                    {
-                        return Some(response);
+                    .await;
+                    match result {
+                        // The account is gone: start over with a new one.
+                        // Other errors, like a wrong secret, are shown and the account is kept.
+                        Err(e) if e.is_account_lost() => {
+                            warn!("Could not reuse client {client_id} ({e}), creating a new one.");
+                            create_client_from_auth_backend(auth_url, secret).await
+                        }
+                        result => result,
                     }
-                    create_client_from_auth_backend(auth_backend_addr, secret).await
-                })
+                }
+                .boxed_local()
             } else {
                 info!("Create a new client and get its token.");
-                // FIXME: use a random string
-                let secret = "".to_string();
-                prefs.secret = Some(secret.clone());
-                IoTaskPool::get().spawn_local(async move {
-                    create_client_from_auth_backend(auth_backend_addr, secret).await
-                })
+                let secret = account.secret.clone().unwrap_or_default();
+                create_client_from_auth_backend(auth_url, secret).boxed_local()
             };
+            let task = IoTaskPool::get().spawn_local(async move {
+                let auth_url = target.auth_url.clone();
+                let (server_addr, certificate_digest) = fetch_connection_details(target).await?;
+                let token_response = token_task.await?;
+                Ok(ConnectInfo {
+                    auth_url,
+                    token_response,
+                    server_addr,
+                    certificate_digest,
+                })
+            });
             task_state.task = Some(task);
         }
         _ => {
@@ -4,6 +4,8 @@ use lightyear::connection::client::ClientState;
 use lightyear::prelude::client::*;
 use lightyear::prelude::*;
 
+use shared::client::map::{MapRejected, ServerMap};
+
 pub struct ExampleClientRendererPlugin {
     /// The name of the example, which must also match the edgegap application name.
     pub name: String,
@@ -26,6 +28,7 @@ impl Plugin for ExampleClientRendererPlugin {
         spawn_connect_button(app);
         app.add_systems(Update, update_button_text);
         app.add_observer(on_update_status_message);
+        app.add_observer(show_map_rejection);
         app.add_observer(handle_connection);
         app.add_observer(handle_disconnection);
     }
@@ -48,6 +51,10 @@ fn on_update_status_message(
     }
 }
 
+fn show_map_rejection(trigger: On<MapRejected>, mut commands: Commands) {
+    commands.trigger(UpdateStatusMessage(trigger.event().reason.clone()));
+}
+
 #[derive(Component)]
 struct StatusMessageMarker;
 
@@ -170,15 +177,16 @@ pub(crate) fn handle_disconnection(
     mut commands: Commands,
     debug_text: Query<Entity, With<ClientIdText>>,
     disconnected: Query<(Entity, &Disconnected)>,
+    server_map: Res<ServerMap>,
 ) {
-    commands.trigger(UpdateStatusMessage(format!(
-        "Disconnected ({})",
+    let reason = server_map.rejection.clone().unwrap_or_else(|| {
         disconnected
             .get(trigger.entity)
-            .map(|d| d.1.reason.as_ref())
-            .unwrap_or(None)
-            .unwrap_or(&"Unknown".to_string())
-    )));
+            .ok()
+            .and_then(|d| d.1.reason.clone())
+            .unwrap_or_else(|| "Unknown".to_string())
+    });
+    commands.trigger(UpdateStatusMessage(format!("Disconnected ({reason})")));
     for entity in debug_text.iter() {
         commands.entity(entity).despawn();
     }
@@ -21,8 +21,6 @@ pub struct ExampleClient {
     pub client_id: u64,
     /// The client port to listen on
     pub client_port: u16,
-    /// The socket address of the server
-    pub server_addr: SocketAddr,
     /// Possibly add a conditioner to simulate network conditions
     pub conditioner: Option<RecvLinkConditioner>,
     pub shared: SharedSettings,
@@ -39,23 +37,12 @@ impl ExampleClient {
                 Client::default(),
                 Link::new(settings.conditioner.clone()),
                 LocalAddr(client_addr),
-                PeerAddr(settings.server_addr),
                 ReplicationReceiver::default(),
                 PredictionManager::default(),
                 Name::from("Client"),
             ));
-
-            let certificate_digest = {
-                #[cfg(target_family = "wasm")]
-                {
-                    include_str!("../../../certificates/digest.txt").to_string()
-                }
-                #[cfg(not(target_family = "wasm"))]
-                {
-                    include_str!("../../../certificates/digest.txt").to_string()
-                }
-            };
-            entity_mut.insert(WebTransportClientIo { certificate_digest });
+            // The server address and the WebTransport io are only known once connection settings
+            // are resolved, they are added by the auth plugin before connecting.
 
             Ok(())
         });
@@ -13,8 +13,10 @@ mod client;
 mod client_renderer;
 mod common_client;
 mod renderer;
+mod settings;
 
 use bevy::log::{Level, LogPlugin};
+use bevy::platform::collections::HashMap;
 use bevy::prelude::*;
 use bevy::window::PresentMode;
 use bevy::winit::WinitSettings;
@@ -25,15 +27,23 @@ use lightyear::prelude::client::InputDelayConfig;
 use lightyear::prelude::*;
 use shared::SharedPlugin;
 use shared::auth::TokenResponse;
-use shared::settings::{CLIENT_PORT, FIXED_TIMESTEP_HZ, SERVER_ADDR, SHARED_SETTINGS};
+use shared::settings::{CLIENT_PORT, FIXED_TIMESTEP_HZ, SHARED_SETTINGS};
 
 use crate::auth::AuthClientPlugin;
 use crate::client::ExampleClientPlugin;
 use crate::client_renderer::ExampleClientRendererPlugin;
 use crate::common_client::{ExampleClient, connect};
+use crate::settings::{ClientArgs, ConnectionPrefs};
 
+/// The account on each auth backend, keyed by its URL: a client id only exists on the backend
+/// that created it.
 #[derive(Resource, Reflect, Clone, Default)]
 struct AuthPrefs {
+    pub accounts: HashMap<String, AuthAccount>,
+}
+
+#[derive(Reflect, Clone, Default)]
+struct AuthAccount {
     pub last_token: Option<TokenResponse>,
     pub secret: Option<String>,
 }
@@ -41,11 +51,14 @@ struct AuthPrefs {
 #[derive(Reflect, Prefs, Default)]
 struct MyPrefs {
     pub token: AuthPrefs,
+    pub connection: ConnectionPrefs,
 }
 
 /// When running the example as a binary, we only support Client or Server mode.
 fn main() {
+    let args = ClientArgs::from_env();
     let mut app = new_gui_app();
+    app.insert_resource(args);
     app.add_plugins((
         lightyear::prelude::client::ClientPlugins {
             tick_duration: Duration::from_secs_f64(1.0 / FIXED_TIMESTEP_HZ),
@@ -53,15 +66,13 @@ fn main() {
         ExampleClientRendererPlugin::new(format!("Client")),
     ));
     app.add_plugins(SharedPlugin);
-    app.add_plugins(AuthClientPlugin {
-        auth_backend_address: shared::auth::AUTH_BACKEND_ADDRESS,
-    });
+    app.add_plugins(AuthClientPlugin);
+    app.add_plugins(shared::client::map::plugin);
     app.add_plugins(PrefsPlugin::<MyPrefs>::default());
     app.world_mut()
         .spawn(ExampleClient {
             client_id: 0,
             client_port: CLIENT_PORT,
-            server_addr: SERVER_ADDR,
             conditioner: Some(RecvLinkConditioner::new(LinkConditionerConfig {
                 incoming_latency: Duration::from_secs_f32(0.15),
                 incoming_jitter: Duration::from_secs_f32(0.1),
@@ -90,6 +101,8 @@ pub fn new_gui_app() -> App {
             .set(AssetPlugin {
                 // https://github.com/bevyengine/bevy/issues/10157
                 meta_check: bevy::asset::AssetMetaCheck::Never,
+                #[cfg(not(target_family = "wasm"))]
+                file_path: shared::settings::ASSETS_PATH.to_string(),
                 ..default()
             })
             .set(log_plugin())
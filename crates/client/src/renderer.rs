@@ -1,10 +1,12 @@
 use avian2d::prelude::{Position, Rotation};
-use bevy::color::palettes::basic::GREEN;
+use bevy::color::palettes::basic::{GREEN, RED};
 use bevy::prelude::*;
 use lightyear::prelude::*;
 use lightyear_frame_interpolation::{FrameInterpolate, FrameInterpolationPlugin};
+use shared::color_from_id;
 use shared::game::Wall;
-use shared::protocol::physics::PLAYER_SIZE;
+use shared::game::map::LoadedMap;
+use shared::protocol::physics::{PLAYER_SIZE, PROJECTILE_SIZE};
 use shared::protocol::*;
 
 #[cfg(feature = "debug")]
@@ -22,10 +24,14 @@ impl Plugin for ExampleRendererPlugin {
         app.add_systems(Startup, init);
 
         // draw after interpolation is done
-        app.add_systems(PostStartup, draw_walls_retained);
+        // walls are spawned once the map is loaded, and replaced when it changes
         app.add_systems(
             PostUpdate,
-            (draw_players, draw_circles)
+            draw_walls_retained.run_if(resource_changed_or_removed::<LoadedMap>),
+        );
+        app.add_systems(
+            PostUpdate,
+            (draw_players, draw_circles, draw_projectiles)
                 .after(InterpolationSystems::Interpolate)
                 .after(RollbackSystems::VisualCorrection),
         );
@@ -113,11 +119,28 @@ fn add_frame_interpolation_components(
     }
 }
 
+/// Alpha of players waiting to respawn
+const DEAD_PLAYER_ALPHA: f32 = 0.2;
+
 pub(crate) fn draw_players(
     mut gizmos: Gizmos,
-    players: Query<(&Position, &Rotation, &ColorComponent), With<PlayerId>>,
+    players: Query<
+        (
+            &Position,
+            &Rotation,
+            &ColorComponent,
+            Option<&Health>,
+            Has<Dead>,
+        ),
+        With<PlayerId>,
+    >,
 ) {
-    for (position, rotation, color) in &players {
+    for (position, rotation, color, health, dead) in &players {
+        let color = if dead {
+            color.0.with_alpha(DEAD_PLAYER_ALPHA)
+        } else {
+            color.0
+        };
         gizmos.circle_2d(
             Isometry2d {
                 rotation: Rot2 {
@@ -127,28 +150,53 @@ pub(crate) fn draw_players(
                 translation: Vec2::new(position.x, position.y),
             },
             PLAYER_SIZE,
-            color.0,
+            color,
         );
+        // health bar above the player
+        if let Some(health) = health
+            && !dead
+        {
+            let ratio = health.current as f32 / health.max.max(1) as f32;
+            let start = position.0 + Vec2::new(-PLAYER_SIZE, PLAYER_SIZE + 4.0);
+            gizmos.line_2d(
+                start,
+                start + Vec2::X * 2.0 * PLAYER_SIZE,
+                RED.with_alpha(0.5),
+            );
+            gizmos.line_2d(start, start + Vec2::X * 2.0 * PLAYER_SIZE * ratio, GREEN);
+        }
     }
 }
 
+/// Gizmo drawing a wall of the loaded map.
+#[derive(Component)]
+pub(crate) struct WallGizmo;
+
+/// Replace the wall gizmos with the walls of the loaded map.
 pub(crate) fn draw_walls_retained(
     mut commands: Commands,
     mut gizmo_assets: ResMut<Assets<GizmoAsset>>,
     walls: Query<(&Wall, &ColorComponent), Without<PlayerId>>,
+    previous: Query<Entity, With<WallGizmo>>,
 ) {
+    for entity in &previous {
+        commands.entity(entity).despawn();
+    }
     for (wall, color) in &walls {
         let mut gizmo = GizmoAsset::default();
 
         gizmo.line_2d(wall.start, wall.end, color.0);
-        commands.spawn(Gizmo {
-            handle: gizmo_assets.add(gizmo),
-            line_config: GizmoLineConfig {
-                width: 1.,
+        commands.spawn((
+            Gizmo {
+                handle: gizmo_assets.add(gizmo),
+                line_config: GizmoLineConfig {
+                    width: 1.,
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        });
+            WallGizmo,
+        ));
     }
 }
 
@@ -158,3 +206,14 @@ pub(crate) fn draw_circles(mut gizmos: Gizmos, circles: Query<&Position, With<Ci
         gizmos.circle_2d(Isometry2d::from_translation(position.0), 10.0, GREEN);
     }
 }
+
+/// System that draws projectiles, with the color of their owner
+pub(crate) fn draw_projectiles(mut gizmos: Gizmos, projectiles: Query<(&Position, &Projectile)>) {
+    for (position, projectile) in &projectiles {
+        gizmos.circle_2d(
+            Isometry2d::from_translation(position.0),
+            PROJECTILE_SIZE,
+            color_from_id(projectile.owner),
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Runtime configuration of the servers the client connects to.
+//!
+//! Each setting is resolved when pressing Connect, in this order:
+//! - command line flag (or its environment variable), native only
+//! - the prefs file
+//! - what the auth backend advertises on `/server_info`, or a default targeting a local server
+use core::net::SocketAddr;
+
+use bevy::prelude::*;
+use clap::Parser;
+use shared::auth::AUTH_BACKEND_URL;
+
+/// Command line flags, each of them can also be set through an environment variable.
+#[derive(Resource, Parser, Clone, Debug, Default)]
+#[command(about = "Game client")]
+pub struct ClientArgs {
+    /// Address of the game server [default: advertised by the auth backend]
+    #[arg(long, env = "CLIENT_SERVER_ADDR")]
+    pub server_addr: Option<SocketAddr>,
+    /// Base url of the authentication backend, eg: https://auth.example.com
+    #[arg(long, env = "CLIENT_AUTH_URL")]
+    pub auth_url: Option<String>,
+    /// Hex encoded sha256 digest of the game server certificate [default: advertised by the auth backend]
+    #[arg(long, env = "CLIENT_CERT_DIGEST", conflicts_with = "cert_digest_url")]
+    pub cert_digest: Option<String>,
+    /// Url serving the hex encoded sha256 digest of the game server certificate
+    #[arg(long, env = "CLIENT_CERT_DIGEST_URL")]
+    pub cert_digest_url: Option<String>,
+}
+
+impl ClientArgs {
+    /// Parse the process arguments. There are none on the web, so defaults are used.
+    pub fn from_env() -> Self {
+        #[cfg(target_family = "wasm")]
+        {
+            Self::default()
+        }
+        #[cfg(not(target_family = "wasm"))]
+        {
+            Self::parse()
+        }
+    }
+}
+
+/// Connection settings persisted in the prefs file, so that they can be edited without rebuilding.
+#[derive(Resource, Reflect, Clone, Default)]
+pub struct ConnectionPrefs {
+    pub server_addr: Option<String>,
+    pub auth_url: Option<String>,
+    pub certificate_digest: Option<String>,
+    pub certificate_digest_url: Option<String>,
+}
+
+/// Where to get the certificate digest of the game server from.
+#[derive(Clone, Debug)]
+pub enum DigestSource {
+    Known(String),
+    /// Fetched with a GET request, the body is the digest.
+    Url(String),
+    /// Advertised by the auth backend, so that regenerating the certificate doesn't require
+    /// updating clients.
+    ServerInfo,
+}
+
+/// The resolved connection settings.
+#[derive(Clone, Debug)]
+pub struct ConnectionTarget {
+    /// If `None`, the address advertised by the auth backend is used.
+    pub server_addr: Option<SocketAddr>,
+    pub auth_url: String,
+    pub certificate_digest: DigestSource,
+}
+
+impl ConnectionTarget {
+    pub fn resolve(args: &ClientArgs, prefs: &ConnectionPrefs) -> Self {
+        let server_addr = args.server_addr.or_else(|| {
+            let addr = prefs.server_addr.as_ref()?;
+            addr.parse()
+                .inspect_err(|e| error!("Invalid server address in prefs {addr:?}: {e}"))
+                .ok()
+        });
+        let auth_url = args
+            .auth_url
+            .clone()
+            .or_else(|| prefs.auth_url.clone())
+            .unwrap_or_else(|| AUTH_BACKEND_URL.to_string());
+        let certificate_digest = if let Some(digest) = &args.cert_digest {
+            DigestSource::Known(digest.clone())
+        } else if let Some(url) = &args.cert_digest_url {
+            DigestSource::Url(url.clone())
+        } else if let Some(digest) = &prefs.certificate_digest {
+            DigestSource::Known(digest.clone())
+        } else if let Some(url) = &prefs.certificate_digest_url {
+            DigestSource::Url(url.clone())
+        } else {
+            DigestSource::ServerInfo
+        };
+        Self {
+            server_addr,
+            // avoid double slashes when joining paths
+            auth_url: auth_url.trim_end_matches('/').to_string(),
+            certificate_digest,
+        }
+    }
+
+    /// Whether `/server_info` has to be fetched to complete these settings.
+    pub fn needs_server_info(&self) -> bool {
+        self.server_addr.is_none() || matches!(self.certificate_digest, DigestSource::ServerInfo)
+    }
+}
@@ -0,0 +1,77 @@
+//! Generates the key used by the auth backend to sign netcode connect tokens,
+//! and by the game server to read them.
+//!
+//! Rotating the key invalidates the connect tokens already handed out: clients get a new one
+//! from the auth backend the next time they connect.
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Parser;
+use rand::RngCore;
+
+const KEY_BYTES: usize = 32;
+
+#[derive(Parser, Debug)]
+#[command(about = "Generate the private key signing netcode connect tokens")]
+struct Cli {
+    /// Where to write the key
+    #[arg(default_value = "private.key")]
+    path: PathBuf,
+    /// Replace an existing key, which is kept as `<path>.<timestamp>.bak` (git ignored)
+    #[arg(long)]
+    rotate: bool,
+    /// Also print the key hex encoded, to set it in the SERVER_PRIVATE_KEY environment variable
+    #[arg(long)]
+    print_hex: bool,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if cli.path.exists() {
+        if !cli.rotate {
+            eprintln!(
+                "{} already exists, use --rotate to replace it",
+                cli.path.display()
+            );
+            std::process::exit(1);
+        }
+        let backup = backup_path(&cli.path);
+        std::fs::rename(&cli.path, &backup).expect("could not back up the previous key.");
+        println!("Previous key moved to {}", backup.display());
+    }
+
+    let mut key = [0u8; KEY_BYTES];
+    // a zero key would be refused by the server
+    while key == [0u8; KEY_BYTES] {
+        rand::rng().fill_bytes(&mut key);
+    }
+    write_private(&cli.path, &key).expect("could not write private key.");
+    println!("Private key written to {}", cli.path.display());
+    if cli.print_hex {
+        let hex: String = key.iter().map(|byte| format!("{byte:02x}")).collect();
+        println!("SERVER_PRIVATE_KEY={hex}");
+    }
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{timestamp}.bak"));
+    backup.into()
+}
+
+/// Writes the key readable by its owner only.
+fn write_private(path: &Path, key: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(key)
+}
@@ -1,4 +1,3 @@
-use aeronet_webtransport::wtransport::tls::Sha256DigestFmt;
 use lightyear::prelude::Identity;
 
 #[path = "./src/certificate.rs"]
@@ -9,8 +8,7 @@ async fn main() {
     let settings = certificate::WebTransportCertificateSettings::default();
     let identity = Identity::from(&settings);
     let cert = identity.certificate_chain();
-    let digest = cert.as_slice()[0].hash();
-    let digest = digest.fmt(Sha256DigestFmt::DottedHex).replace(":", "");
+    let digest = certificate::certificate_digest(&identity);
 
     std::fs::write("digest.txt", digest).expect("could not write digest.");
     cert.store_pemfile("cert.pem")
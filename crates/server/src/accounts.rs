@@ -0,0 +1,285 @@
+//! Storage for the accounts known by the authentication backend.
+//!
+//! The auth backend only needs to know which `client_id`s exist and the secret associated with them,
+//! but this information has to survive server restarts: clients persist their `client_id` in their
+//! preferences and expect to be able to reuse it.
+//!
+//! Secrets are never stored in plaintext: only a salted argon2 hash is kept.
+extern crate alloc;
+use alloc::sync::Arc;
+use core::fmt;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+use argon2::Argon2;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use serde::{Deserialize, Serialize};
+use shared::auth::Role;
+
+/// An account registered through `/create_client`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    /// Argon2 hash of the client secret, in PHC string format (includes the salt and parameters).
+    #[serde(default)]
+    secret_hash: String,
+    /// Plaintext secret written by older versions of the store.
+    /// It is hashed when the store is opened, and never written back.
+    #[serde(default, skip_serializing, rename = "secret")]
+    legacy_secret: Option<String>,
+    /// Name shown to other players.
+    #[serde(default)]
+    pub display_name: String,
+    /// Only changed by editing the store.
+    #[serde(default)]
+    pub role: Role,
+}
+
+impl Account {
+    /// Creates an account with the [`Role::Player`] role, hashing `secret` with a random salt.
+    pub fn new(secret: &str, display_name: String) -> Result<Self, AccountStoreError> {
+        Ok(Self {
+            secret_hash: hash_secret(secret)?,
+            legacy_secret: None,
+            display_name,
+            role: Role::Player,
+        })
+    }
+
+    /// Checks `secret` against the stored hash.
+    ///
+    /// The comparison is done in constant time by argon2.
+    pub fn verify_secret(&self, secret: &str) -> bool {
+        let Ok(hash) = PasswordHash::new(&self.secret_hash) else {
+            return false;
+        };
+        Argon2::default()
+            .verify_password(secret.as_bytes(), &hash)
+            .is_ok()
+    }
+
+    /// Hashes the plaintext secret of an account stored by an older version.
+    ///
+    /// Returns true if the account was migrated.
+    fn migrate_legacy_secret(&mut self) -> Result<bool, AccountStoreError> {
+        let Some(secret) = self.legacy_secret.take() else {
+            return Ok(false);
+        };
+        self.secret_hash = hash_secret(&secret)?;
+        Ok(true)
+    }
+}
+
+fn hash_secret(secret: &str) -> Result<String, AccountStoreError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(AccountStoreError::Hashing)
+}
+
+/// A storage backend for [`Account`]s.
+///
+/// Implementations must be safe to share between the axum handlers and the bevy app.
+pub trait AccountStore: Send + Sync + 'static {
+    /// Returns true if an account exists for this `client_id`.
+    fn contains(&self, client_id: u64) -> bool;
+
+    /// Returns the account registered for this `client_id`, if any.
+    fn get(&self, client_id: u64) -> Option<Account>;
+
+    /// Registers a new account.
+    ///
+    /// Returns `Ok(false)` without modifying the store if the `client_id` is already taken.
+    fn insert(&self, client_id: u64, account: Account) -> Result<bool, AccountStoreError>;
+}
+
+/// The account store shared between the auth backend and the server app.
+pub type SharedAccountStore = Arc<dyn AccountStore>;
+
+#[derive(Debug)]
+pub enum AccountStoreError {
+    Io(std::io::Error),
+    Serialization(serde_json::Error),
+    Hashing(argon2::password_hash::Error),
+    /// The lock protecting the accounts was poisoned by a panicking thread.
+    Poisoned,
+}
+
+impl fmt::Display for AccountStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountStoreError::Io(e) => write!(f, "account store io error: {e}"),
+            AccountStoreError::Serialization(e) => {
+                write!(f, "account store serialization error: {e}")
+            }
+            AccountStoreError::Hashing(e) => write!(f, "account secret hashing error: {e}"),
+            AccountStoreError::Poisoned => write!(f, "account store lock poisoned"),
+        }
+    }
+}
+
+impl std::error::Error for AccountStoreError {}
+
+impl From<std::io::Error> for AccountStoreError {
+    fn from(e: std::io::Error) -> Self {
+        AccountStoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for AccountStoreError {
+    fn from(e: serde_json::Error) -> Self {
+        AccountStoreError::Serialization(e)
+    }
+}
+
+/// On-disk representation of the [`FileAccountStore`].
+#[derive(Default, Serialize, Deserialize)]
+struct AccountsFile {
+    accounts: HashMap<u64, Account>,
+}
+
+/// An account appended to the journal of the [`FileAccountStore`].
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    client_id: u64,
+    account: Account,
+}
+
+/// Keeps accounts in memory and persists them to a json file.
+///
+/// New accounts are appended to a journal next to the file (one json line each), so that an
+/// insert doesn't rewrite every account. The journal is merged into the file when the store
+/// is opened.
+pub struct FileAccountStore {
+    accounts: RwLock<HashMap<u64, Account>>,
+    journal: Mutex<File>,
+}
+
+impl FileAccountStore {
+    /// Opens the store at `path`, creating an empty one if the file doesn't exist yet.
+    ///
+    /// Accounts with a plaintext secret are migrated to a hashed secret, and the file is rewritten.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, AccountStoreError> {
+        let path = path.into();
+        let mut accounts = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice::<AccountsFile>(&bytes)?.accounts,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let journal_path = path.with_extension("json.journal");
+        let journaled = read_journal(&journal_path, &mut accounts)?;
+        let mut migrated = 0;
+        for account in accounts.values_mut() {
+            if account.migrate_legacy_secret()? {
+                migrated += 1;
+            }
+        }
+        if migrated > 0 {
+            bevy::log::info!("Hashed {migrated} plaintext account secrets");
+        }
+        if journaled > 0 || migrated > 0 {
+            write_accounts(&path, &accounts)?;
+        }
+        // the journal is only truncated once its accounts are safely in the file
+        let journal = File::create(&journal_path)?;
+        Ok(Self {
+            accounts: RwLock::new(accounts),
+            journal: Mutex::new(journal),
+        })
+    }
+
+    pub fn account_count(&self) -> usize {
+        self.accounts.read().map_or(0, |accounts| accounts.len())
+    }
+}
+
+/// Adds the accounts of the journal at `path`, returns how many there were.
+fn read_journal(
+    path: &Path,
+    accounts: &mut HashMap<u64, Account>,
+) -> Result<usize, AccountStoreError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+    let mut count = 0;
+    while let Some(line) = lines.next() {
+        match serde_json::from_str::<JournalEntry>(line) {
+            Ok(entry) => {
+                accounts.insert(entry.client_id, entry.account);
+                count += 1;
+            }
+            // a crash while appending can only cut the last line
+            Err(e) if lines.peek().is_none() => {
+                bevy::log::warn!(
+                    "Ignoring the incomplete last line of {}: {e}",
+                    path.display()
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(count)
+}
+
+/// Writes the accounts to a temporary file, then moves it over the store file,
+/// so that a crash mid-write can't corrupt existing accounts.
+fn write_accounts(path: &Path, accounts: &HashMap<u64, Account>) -> Result<(), AccountStoreError> {
+    let file = AccountsFile {
+        accounts: accounts.clone(),
+    };
+    let bytes = serde_json::to_vec_pretty(&file)?;
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, bytes)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+impl AccountStore for FileAccountStore {
+    fn contains(&self, client_id: u64) -> bool {
+        self.accounts
+            .read()
+            .is_ok_and(|accounts| accounts.contains_key(&client_id))
+    }
+
+    fn get(&self, client_id: u64) -> Option<Account> {
+        self.accounts.read().ok()?.get(&client_id).cloned()
+    }
+
+    fn insert(&self, client_id: u64, account: Account) -> Result<bool, AccountStoreError> {
+        let mut line = serde_json::to_vec(&JournalEntry {
+            client_id,
+            account: account.clone(),
+        })?;
+        line.push(b'\n');
+        let mut accounts = self
+            .accounts
+            .write()
+            .map_err(|_| AccountStoreError::Poisoned)?;
+        if accounts.contains_key(&client_id) {
+            return Ok(false);
+        }
+        let mut journal = self
+            .journal
+            .lock()
+            .map_err(|_| AccountStoreError::Poisoned)?;
+        let len = journal.stream_position()?;
+        if let Err(e) = journal.write_all(&line).and_then(|()| journal.sync_data()) {
+            // drop the partial line, so that the next ones can still be read
+            journal.set_len(len)?;
+            journal.seek(SeekFrom::Start(len))?;
+            return Err(e.into());
+        }
+        accounts.insert(client_id, account);
+        Ok(true)
+    }
+}
@@ -9,40 +9,117 @@
 extern crate alloc;
 use alloc::sync::Arc;
 use async_compat::Compat;
-use axum::http::{Method, StatusCode};
+use axum::http::{HeaderValue, Method, StatusCode, header};
 use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
-use axum::{Json, Router};
+use axum::{Json, Router, middleware};
+use core::fmt;
 use core::net::SocketAddr;
+use core::time::Duration;
 use lightyear::connection::client::Disconnecting;
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use shared::auth::{AuthPayload, Key, NewClientPayload, TokenResponse};
-use std::sync::{LazyLock, RwLock};
+use shared::auth::{
+    AuthErrorBody, AuthErrorCode, AuthPayload, Key, MAX_DISPLAY_NAME_LEN, MIN_CLIENT_SECRET_LEN,
+    NewClientPayload, ServerInfo, TokenResponse, UserData,
+};
+use std::path::{Path, PathBuf};
 use tower_http::cors::{Any, CorsLayer};
 
-use bevy::platform::collections::{HashMap, HashSet};
 use bevy::prelude::*;
 use bevy::tasks::IoTaskPool;
-use lightyear::netcode::ConnectToken;
+use lightyear::netcode::{ConnectToken, NetcodeServer, PRIVATE_KEY_BYTES};
 use lightyear::prelude::server::*;
 use lightyear::prelude::*;
-use shared::settings::SHARED_SETTINGS;
-
-pub static PRIVATE_KEY: LazyLock<Key> = LazyLock::new(|| {
-    std::fs::read("private.key")
-        .ok()
-        .and_then(|bytes| bytes.try_into().ok())
-        .unwrap_or_else(|| {
-            error!("Private key is null, be careful on prod!");
-            [0u8; 32]
+
+use crate::accounts::{Account, SharedAccountStore};
+use crate::config::{RateLimitConfig, ServerConfig};
+use crate::rate_limit::{RateLimits, SharedRateLimits, limit_by_ip};
+
+#[derive(Debug)]
+pub enum PrivateKeyError {
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    InvalidLength {
+        path: PathBuf,
+        len: usize,
+    },
+    /// The key is all zeros, as written by older versions or test setups.
+    Zero,
+}
+
+impl fmt::Display for PrivateKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrivateKeyError::Read { path, error } => write!(
+                f,
+                "could not read private key {}: {error}, \
+                 generate it with `cargo run --bin generate_auth_private_key`",
+                path.display()
+            ),
+            PrivateKeyError::InvalidLength { path, len } => write!(
+                f,
+                "private key {} is {len} bytes long, expected {PRIVATE_KEY_BYTES}",
+                path.display()
+            ),
+            PrivateKeyError::Zero => write!(f, "private key is all zeros"),
+        }
+    }
+}
+
+impl std::error::Error for PrivateKeyError {}
+
+/// Get the key used to sign connect tokens: from the config if set, otherwise from the key file.
+///
+/// Anyone knowing the key can forge connect tokens, so a missing or zero key is refused,
+/// unless `allow_insecure_key` is set.
+pub fn load_private_key(config: &ServerConfig) -> Result<Key, PrivateKeyError> {
+    let key = match config.private_key {
+        Some(key) => Ok(key),
+        None => read_private_key(&config.private_key_path),
+    };
+    match key {
+        Ok(key) if key != Key::default() => Ok(key),
+        Ok(_) if !config.allow_insecure_key => Err(PrivateKeyError::Zero),
+        Err(e) if !config.allow_insecure_key => Err(e),
+        _ => {
+            warn!("Using a zero private key, anyone can forge connect tokens!");
+            Ok(Key::default())
+        }
+    }
+}
+
+fn read_private_key(path: &Path) -> Result<Key, PrivateKeyError> {
+    let bytes = std::fs::read(path).map_err(|error| PrivateKeyError::Read {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let len = bytes.len();
+    bytes
+        .try_into()
+        .map_err(|_| PrivateKeyError::InvalidLength {
+            path: path.to_path_buf(),
+            len,
         })
-});
+}
 
 pub struct AuthServerPlugin {
     pub game_server_addr: SocketAddr,
     pub auth_backend_addr: SocketAddr,
+    pub protocol_id: u64,
+    pub private_key: Key,
+    /// Seconds a connect token can be used to connect, after it is generated
+    pub token_expire_secs: i32,
+    /// Seconds without packets before the connection times out, negative to never time out
+    pub token_timeout_secs: i32,
+    /// Digest of the game server certificate, advertised to clients on `/server_info`
+    pub certificate_digest: String,
+    /// Where accounts are persisted between server restarts.
+    pub accounts: SharedAccountStore,
+    /// Limits on the requests to the auth backend
+    pub rate_limits: RateLimitConfig,
 }
 
 impl Plugin for AuthServerPlugin {
@@ -50,59 +127,90 @@ impl Plugin for AuthServerPlugin {
         app.add_observer(handle_disconnect_event);
         app.add_observer(handle_connect_event);
 
-        let client_ids = Arc::new(RwLock::new(HashSet::default()));
-        let client_secrets = Arc::new(RwLock::new(HashMap::default()));
         start_netcode_authentication_task(
-            self.game_server_addr,
+            TokenSettings {
+                game_server_addr: self.game_server_addr,
+                protocol_id: self.protocol_id,
+                private_key: self.private_key,
+                expire_secs: self.token_expire_secs,
+                timeout_secs: self.token_timeout_secs,
+            },
+            ServerInfo {
+                certificate_digest: self.certificate_digest.clone(),
+                game_server_addr: self.game_server_addr,
+                protocol_id: self.protocol_id,
+            },
             self.auth_backend_addr,
-            client_ids.clone(),
-            client_secrets.clone(),
+            self.accounts.clone(),
+            Arc::new(RateLimits::new(&self.rate_limits)),
         );
-        app.insert_resource(ClientIds(client_ids));
+        app.insert_resource(Accounts(self.accounts.clone()));
     }
 }
 
-/// This resource will track the list of Netcode client-ids currently in use, so that
-/// we don't have multiple clients with the same id
-/// it maps to a unique id to fetch into database.
-#[derive(Resource, Default)]
-struct ClientIds(Arc<RwLock<HashSet<u64>>>);
+/// This resource gives access to the accounts known by the auth backend.
+/// Netcode client-ids are unique per account, and persist between sessions.
+#[derive(Resource)]
+pub struct Accounts(pub SharedAccountStore);
+
+/// The account of a connected client, read from its connect token.
+/// Added on the client's connection entity, and on its player.
+#[derive(Component, Debug, Clone)]
+pub struct ClientAccount(pub UserData);
 
-/// Update the list of connected client ids when a client disconnects
+/// Log when a client disconnects
 fn handle_disconnect_event(
     trigger: On<Add, Disconnected>,
     query: Query<&RemoteId, With<ClientOf>>,
-    //client_ids: Res<ClientIds>,
 ) {
     let Ok(remote_id) = query.get(trigger.entity) else {
         return;
     };
     if let PeerId::Netcode(client_id) = remote_id.0 {
-        info!(
-            "Client disconnected: {}. Removing from ClientIds.",
-            client_id
-        );
-        // TODO: track list of connected clients? I guess we can just use ecs :thinking:
-        // We don't want to remove clients because their Ids should persist between sessions.
-        //client_ids.0.write().unwrap().remove(&client_id);
+        // We don't remove the account: client ids persist between sessions.
+        info!("Client disconnected: {}.", client_id);
     } else {
         // ?
     }
 }
 
-/// Update the list of connected client ids when a client connects
+/// Check that connecting clients are authenticated with a known account,
+/// and read the account details the auth backend put in their connect token.
 fn handle_connect_event(
     trigger: On<Add, Connected>,
     mut commands: Commands,
-    query: Query<&RemoteId, With<ClientOf>>,
-    client_ids: Res<ClientIds>,
+    query: Query<(&RemoteId, &LinkOf), With<ClientOf>>,
+    servers: Query<&NetcodeServer>,
+    accounts: Res<Accounts>,
 ) {
-    let Ok(remote_id) = query.get(trigger.entity) else {
+    let Ok((remote_id, link_of)) = query.get(trigger.entity) else {
         return;
     };
     if let PeerId::Netcode(client_id) = remote_id.0 {
-        info!("Client connected: {}. Adding to ClientIds.", client_id);
-        client_ids.0.write().unwrap().insert(client_id);
+        if !accounts.0.contains(client_id) {
+            // The token was signed with our key, but the account is gone (store was reset?)
+            warn!("Client connected with unknown account: {}.", client_id);
+        }
+        let user_data = servers
+            .get(link_of.server)
+            .ok()
+            .and_then(|server| server.user_data(client_id))
+            .and_then(|bytes| UserData::from_bytes(&bytes));
+        match user_data {
+            Some(user_data) if user_data.account_id == client_id => {
+                info!(
+                    "Client connected: {} ({}, {:?}).",
+                    client_id, user_data.display_name, user_data.role
+                );
+                commands
+                    .entity(trigger.entity)
+                    .insert(ClientAccount(user_data));
+            }
+            _ => {
+                // tokens generated before user data was added, they expire quickly
+                warn!("Client connected without valid user data: {}.", client_id);
+            }
+        }
     } else {
         info!(
             "Client connected but not authenticated! Disconnecting {}",
@@ -116,131 +224,295 @@ fn handle_connect_event(
 pub enum AuthError {
     WrongCredentials,
     MissingCredentials,
+    UnknownClient,
     TokenCreation,
     InvalidToken,
+    WeakSecret,
+    InvalidDisplayName,
+    AccountStorage,
+    /// Rate limited or locked out, with the time to wait before retrying
+    TooManyRequests(Duration),
 }
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AuthError::WrongCredentials => (StatusCode::UNAUTHORIZED, "Wrong credentials"),
-            AuthError::MissingCredentials => (StatusCode::BAD_REQUEST, "Missing credentials"),
-            AuthError::TokenCreation => (StatusCode::INTERNAL_SERVER_ERROR, "Token creation error"),
-            AuthError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
+        let retry_after_secs = match self {
+            // round up, so that retrying right on time succeeds
+            AuthError::TooManyRequests(wait) => {
+                Some(wait.as_secs() + u64::from(wait.subsec_nanos() > 0))
+            }
+            _ => None,
         };
-        let body = Json(json!({
-            "error": error_message,
-        }));
-        (status, body).into_response()
+        let (status, code, error_message) = match self {
+            AuthError::WrongCredentials => (
+                StatusCode::UNAUTHORIZED,
+                AuthErrorCode::WrongCredentials,
+                "Wrong credentials",
+            ),
+            AuthError::MissingCredentials => (
+                StatusCode::BAD_REQUEST,
+                AuthErrorCode::MissingCredentials,
+                "Missing credentials",
+            ),
+            AuthError::UnknownClient => (
+                StatusCode::NOT_FOUND,
+                AuthErrorCode::UnknownClient,
+                "Unknown client",
+            ),
+            AuthError::TokenCreation => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                AuthErrorCode::TokenCreation,
+                "Token creation error",
+            ),
+            AuthError::InvalidToken => (
+                StatusCode::BAD_REQUEST,
+                AuthErrorCode::InvalidToken,
+                "Invalid token",
+            ),
+            AuthError::WeakSecret => (
+                StatusCode::BAD_REQUEST,
+                AuthErrorCode::WeakSecret,
+                "Client secret is too short",
+            ),
+            AuthError::InvalidDisplayName => (
+                StatusCode::BAD_REQUEST,
+                AuthErrorCode::InvalidDisplayName,
+                "Display name is too long",
+            ),
+            AuthError::AccountStorage => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                AuthErrorCode::AccountStorage,
+                "Account storage error",
+            ),
+            AuthError::TooManyRequests(_) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                AuthErrorCode::TooManyRequests,
+                "Too many requests",
+            ),
+        };
+        let body = Json(AuthErrorBody {
+            code,
+            error: error_message.to_string(),
+            retry_after_secs,
+        });
+        let mut response = (status, body).into_response();
+        if let Some(retry_after) = retry_after_secs {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
     }
 }
 
 async fn create_client(
-    client_ids: axum::extract::Extension<Arc<RwLock<HashSet<u64>>>>,
-    client_secrets: axum::extract::Extension<Arc<RwLock<HashMap<u64, String>>>>,
-    game_server_addr: axum::extract::Extension<GameServerAddr>,
+    axum::extract::Extension(accounts): axum::extract::Extension<SharedAccountStore>,
+    token_settings: axum::extract::Extension<TokenSettings>,
     Json(payload): Json<NewClientPayload>,
-) -> Json<TokenResponse> {
-    // generate a unique client_id
-    let client_id = loop {
-        let id = rand::rng().next_u64();
-        let mut ids = client_ids.write().unwrap();
-        if !ids.contains(&id) {
-            ids.insert(id);
-            break id;
-        }
-    };
+) -> Result<Json<TokenResponse>, AuthError> {
+    if payload.client_secret.is_empty() {
+        return Err(AuthError::MissingCredentials);
+    }
+    if payload.client_secret.len() < MIN_CLIENT_SECRET_LEN {
+        return Err(AuthError::WeakSecret);
+    }
+    let display_name = payload
+        .display_name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string);
+    if display_name
+        .as_ref()
+        .is_some_and(|name| name.len() > MAX_DISPLAY_NAME_LEN)
     {
-        client_secrets
-            .write()
-            .unwrap()
-            .insert(client_id, payload.client_secret);
+        return Err(AuthError::InvalidDisplayName);
     }
+    let secret = payload.client_secret;
+    let (client_id, account) = run_blocking(move || {
+        let account = Account::new(&secret, String::new()).map_err(|e| {
+            error!("Failed to hash client secret: {e}");
+            AuthError::AccountStorage
+        })?;
+        // generate a unique client_id, the store refuses ids already in use.
+        loop {
+            let id = rand::rng().next_u64();
+            let mut account = account.clone();
+            account.display_name = display_name
+                .clone()
+                .unwrap_or_else(|| default_display_name(id));
+            match accounts.insert(id, account.clone()) {
+                Ok(true) => return Ok((id, account)),
+                Ok(false) => continue,
+                Err(e) => {
+                    error!("Failed to store new account: {e}");
+                    return Err(AuthError::AccountStorage);
+                }
+            }
+        }
+    })
+    .await?;
 
-    // generate netcode ConnectToken
-    let token = ConnectToken::build(
-        game_server_addr.0.0,
-        SHARED_SETTINGS.protocol_id,
+    Ok(Json(TokenResponse {
+        token: generate_token(&token_settings, client_id, &account)?,
         client_id,
-        *PRIVATE_KEY,
-    )
-    .generate()
-    .expect("Failed to generate token");
+    }))
+}
 
-    let token_bytes = token.try_into_bytes().expect("Failed to serialize token");
+/// Runs `f` on the blocking threads of tokio: argon2 is slow on purpose and the account store
+/// writes to the disk, neither should stall the other requests.
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, AuthError> + Send + 'static,
+) -> Result<T, AuthError> {
+    tokio::task::spawn_blocking(f).await.unwrap_or_else(|e| {
+        error!("Blocking auth task failed: {e}");
+        Err(AuthError::AccountStorage)
+    })
+}
+
+fn default_display_name(client_id: u64) -> String {
+    format!("Player-{:04x}", client_id & 0xffff)
+}
 
-    Json(TokenResponse {
-        token: token_bytes.to_vec(),
+/// Generate a netcode `ConnectToken` for the account, serialized
+fn generate_token(
+    token_settings: &TokenSettings,
+    client_id: u64,
+    account: &Account,
+) -> Result<Vec<u8>, AuthError> {
+    let user_data = UserData {
+        account_id: client_id,
+        display_name: account.display_name.clone(),
+        role: account.role,
+    };
+    let token = ConnectToken::build(
+        token_settings.game_server_addr,
+        token_settings.protocol_id,
         client_id,
-    })
+        token_settings.private_key,
+    )
+    .expire_seconds(token_settings.expire_secs)
+    .timeout_seconds(token_settings.timeout_secs)
+    .user_data(user_data.to_bytes())
+    .generate()
+    .map_err(|e| {
+        error!("Failed to generate token: {e:?}");
+        AuthError::TokenCreation
+    })?;
+    let token_bytes = token.try_into_bytes().map_err(|e| {
+        error!("Failed to serialize token: {e:?}");
+        AuthError::TokenCreation
+    })?;
+    Ok(token_bytes.to_vec())
 }
 
 async fn connect_client(
-    client_secrets: axum::extract::Extension<Arc<RwLock<HashMap<u64, String>>>>,
-    game_server_addr: axum::extract::Extension<GameServerAddr>,
+    accounts: axum::extract::Extension<SharedAccountStore>,
+    token_settings: axum::extract::Extension<TokenSettings>,
+    rate_limits: axum::extract::Extension<SharedRateLimits>,
     Json(payload): Json<AuthPayload>,
 ) -> Result<Json<TokenResponse>, AuthError> {
+    // checked before the secret, so that a locked out client_id can't be brute-forced
+    rate_limits
+        .lockout
+        .check(payload.client_id)
+        .map_err(AuthError::TooManyRequests)?;
+
     // reject connection if client doesn't exist.
-    let Ok(secrets) = client_secrets.read() else {
-        return Err(AuthError::TokenCreation);
-    };
-    let Some(secret) = secrets.get(&payload.client_id).cloned() else {
-        return Err(AuthError::TokenCreation);
+    let Some(account) = accounts.get(payload.client_id) else {
+        return Err(AuthError::UnknownClient);
     };
-    drop(secrets);
+    // only charged for known clients, so that nobody can fill the limiter with made up ids
+    rate_limits
+        .per_client_id
+        .check(payload.client_id)
+        .map_err(AuthError::TooManyRequests)?;
 
-    // FIXME: store hash,
-    if secret != payload.client_secret {
+    let secret = payload.client_secret;
+    let (account, verified) = run_blocking(move || {
+        let verified = account.verify_secret(&secret);
+        Ok((account, verified))
+    })
+    .await?;
+    if !verified {
+        rate_limits.lockout.record_failure(payload.client_id);
         return Err(AuthError::WrongCredentials);
     }
-    // generate netcode ConnectToken
-    let token = ConnectToken::build(
-        game_server_addr.0.0,
-        SHARED_SETTINGS.protocol_id,
-        payload.client_id,
-        *PRIVATE_KEY,
-    )
-    .generate()
-    .expect("Failed to generate token");
-
-    let token_bytes = token.try_into_bytes().expect("Failed to serialize token");
+    rate_limits.lockout.record_success(payload.client_id);
 
     Ok(Json(TokenResponse {
-        token: token_bytes.to_vec(),
+        token: generate_token(&token_settings, payload.client_id, &account)?,
         client_id: payload.client_id,
     }))
 }
 
+/// Tell clients how to reach the game server, so that they don't need to be rebuilt
+/// when the server moves or its certificate is regenerated.
+async fn get_server_info(server_info: axum::extract::Extension<ServerInfo>) -> Json<ServerInfo> {
+    Json(server_info.0)
+}
+
+/// Everything the auth backend needs to generate a `ConnectToken` for the game server.
 #[derive(Clone)]
-pub struct GameServerAddr(pub SocketAddr);
+pub struct TokenSettings {
+    pub game_server_addr: SocketAddr,
+    pub protocol_id: u64,
+    pub private_key: Key,
+    pub expire_secs: i32,
+    pub timeout_secs: i32,
+}
+
+/// The routes of the auth backend.
+///
+/// Requests must carry a `ConnectInfo<SocketAddr>`, see [`Router::into_make_service_with_connect_info`].
+pub(crate) fn auth_router(
+    token_settings: TokenSettings,
+    server_info: ServerInfo,
+    accounts: SharedAccountStore,
+    rate_limits: SharedRateLimits,
+) -> Router {
+    let cors = CorsLayer::new()
+        // allow `GET` and `POST` when accessing the resource
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        // allow requests from any origin
+        .allow_origin(Any)
+        .allow_headers(Any);
+    Router::new()
+        .route("/create_client", post(create_client))
+        .route("/connect_client", post(connect_client))
+        .route_layer(middleware::from_fn_with_state(
+            rate_limits.clone(),
+            limit_by_ip,
+        ))
+        .route("/server_info", get(get_server_info))
+        .layer(cors)
+        .layer(axum::extract::Extension(accounts))
+        .layer(axum::extract::Extension(token_settings))
+        .layer(axum::extract::Extension(server_info))
+        .layer(axum::extract::Extension(rate_limits))
+}
 
 /// Start a detached task that listens for incoming TCP connections and sends `ConnectToken`s to clients
 fn start_netcode_authentication_task(
-    game_server_addr: SocketAddr,
+    token_settings: TokenSettings,
+    server_info: ServerInfo,
     auth_backend_addr: SocketAddr,
-    client_ids: Arc<RwLock<HashSet<u64>>>,
-    client_secrets: Arc<RwLock<HashMap<u64, String>>>,
+    accounts: SharedAccountStore,
+    rate_limits: SharedRateLimits,
 ) {
     IoTaskPool::get()
         .spawn(Compat::new(async move {
-            let cors = CorsLayer::new()
-                // allow `GET` and `POST` when accessing the resource
-                .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-                // allow requests from any origin
-                .allow_origin(Any)
-                .allow_headers(Any);
-            let app = Router::new()
-                .route("/create_client", post(create_client))
-                .route("/connect_client", post(connect_client))
-                .layer(cors)
-                .layer(axum::extract::Extension(client_ids))
-                .layer(axum::extract::Extension(client_secrets))
-                .layer(axum::extract::Extension(GameServerAddr(game_server_addr)));
+            let app = auth_router(token_settings, server_info, accounts, rate_limits);
 
             println!("Auth server listening on http://{}", auth_backend_addr);
             let listener = tokio::net::TcpListener::bind(auth_backend_addr)
                 .await
                 .unwrap();
-            let _ = axum::serve(listener, app).await;
+            // the peer address is needed to rate limit per IP
+            let _ = axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await;
         }))
         .detach();
 }
@@ -1,3 +1,4 @@
+use aeronet_webtransport::wtransport::tls::Sha256DigestFmt;
 use async_compat::Compat;
 use bevy::tasks::IoTaskPool;
 use lightyear::prelude::Identity;
@@ -81,3 +82,11 @@ impl From<&WebTransportCertificateSettings> for Identity {
         }
     }
 }
+
+/// Hex encoded sha256 digest of the leaf certificate, as expected by `WebTransportClientIo`.
+pub fn certificate_digest(identity: &Identity) -> String {
+    identity.certificate_chain().as_slice()[0]
+        .hash()
+        .fmt(Sha256DigestFmt::DottedHex)
+        .replace(':', "")
+}
@@ -17,16 +17,20 @@ use lightyear::netcode::{NetcodeServer, PRIVATE_KEY_BYTES};
 use lightyear::prelude::server::*;
 use lightyear::prelude::*;
 use serde::{Deserialize, Serialize};
-use shared::settings::{SERVER_PORT, SharedSettings};
+use shared::auth::Key;
+use shared::settings::SharedSettings;
 use tracing::warn;
 
-use crate::auth::PRIVATE_KEY;
-use crate::certificate::WebTransportCertificateSettings;
-
-#[derive(Component, Debug)]
+#[derive(Component)]
 #[component(on_add = ExampleServer::on_add)]
 pub struct ExampleServer {
     pub shared: SharedSettings,
+    /// The address the server listens on
+    pub bind_addr: SocketAddr,
+    /// The WebTransport certificate, created beforehand so that its digest can be advertised
+    pub identity: Identity,
+    /// Key used to decrypt the connect tokens signed by the auth backend
+    pub private_key: Key,
 }
 
 impl ExampleServer {
@@ -38,24 +42,17 @@ impl ExampleServer {
             entity_mut.insert((Name::from("Server"),));
 
             let add_netcode = |entity_mut: &mut EntityWorldMut| {
-                // Use private key from settings file.
-                let private_key = *PRIVATE_KEY;
                 entity_mut.insert(NetcodeServer::new(NetcodeConfig {
                     protocol_id: settings.shared.protocol_id,
-                    private_key,
+                    private_key: settings.private_key,
                     ..Default::default()
                 }));
             };
             add_netcode(&mut entity_mut);
-            let server_addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), SERVER_PORT);
             entity_mut.insert((
-                LocalAddr(server_addr),
+                LocalAddr(settings.bind_addr),
                 WebTransportServerIo {
-                    certificate: (&WebTransportCertificateSettings::FromFile {
-                        cert: "../../certificates/cert.pem".to_string(),
-                        key: "../../certificates/key.pem".to_string(),
-                    })
-                        .into(),
+                    certificate: settings.identity,
                 },
             ));
             Ok(())
@@ -0,0 +1,476 @@
+//! Server configuration, read from a TOML file and overridden by CLI flags and environment variables.
+//!
+//! Precedence (highest first): CLI flag, environment variable, config file, default value.
+//! Defaults match the constants in [`shared::settings`], so running without any config behaves
+//! like a local development server.
+use core::fmt;
+use core::net::{Ipv4Addr, SocketAddr};
+use core::time::Duration;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use shared::auth::{AUTH_BACKEND_ADDRESS, Key};
+use shared::game::map::DEFAULT_MAP;
+use shared::game::movement::{MovementSettings, PlayerClass};
+use shared::settings::{
+    ASSETS_PATH, FIXED_TIMESTEP_HZ, SEND_INTERVAL, SERVER_ADDR, SERVER_PORT, SHARED_SETTINGS,
+    SharedSettings,
+};
+
+use crate::certificate::WebTransportCertificateSettings;
+
+/// Config file read when `--config` is not provided. It is optional.
+const DEFAULT_CONFIG_PATH: &str = "server.toml";
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address the game server (WebTransport) listens on.
+    pub bind_addr: SocketAddr,
+    /// Address of the game server as reachable by clients, embedded in connect tokens.
+    pub public_addr: SocketAddr,
+    /// Address the authentication backend (HTTP) listens on.
+    pub auth_bind_addr: SocketAddr,
+    /// Number of simulation ticks per second. Clients must use the same value.
+    pub tick_rate_hz: f64,
+    /// Interval between two replication updates sent to a client, in milliseconds.
+    pub send_interval_ms: u64,
+    /// Identifies the protocol version, clients with a different one are rejected.
+    pub protocol_id: u64,
+    pub certificate: WebTransportCertificateSettings,
+    /// Path to the 32 bytes key used to sign connect tokens,
+    /// generate it with `cargo run --bin generate_auth_private_key`.
+    pub private_key_path: PathBuf,
+    /// Key used to sign connect tokens, takes precedence over `private_key_path`.
+    /// Only settable through the environment or CLI, to keep it out of config files.
+    #[serde(skip)]
+    pub private_key: Option<Key>,
+    /// Start even if the private key is missing or all zeros. Anyone can then forge connect tokens,
+    /// only use this for local development.
+    pub allow_insecure_key: bool,
+    /// Path to the file where accounts are persisted.
+    pub accounts_path: PathBuf,
+    /// Map to play, relative to the assets folder.
+    pub map: String,
+    /// Only replicate entities to a player if no wall is between them, so that clients can't
+    /// reveal players behind walls. Costs a raycast per nearby entity.
+    pub interest_line_of_sight: bool,
+    /// How long the player of a disconnected client is kept, frozen, waiting for it to reconnect.
+    pub reconnect_grace_period_secs: u64,
+    /// Seconds a connect token can be used to connect, after the auth backend generated it.
+    pub token_expire_secs: i32,
+    /// Seconds without packets before a connection times out, negative to never time out.
+    pub token_timeout_secs: i32,
+    pub rate_limit: RateLimitConfig,
+    pub input_validation: InputValidationConfig,
+    /// Class of the players spawned for new clients.
+    pub player_class: PlayerClass,
+    /// How each player class moves, sent to clients so that their prediction matches the server.
+    pub movement: MovementSettings,
+}
+
+/// Limits on the requests to the authentication backend, see [`crate::rate_limit`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Requests an IP address can send at once.
+    pub ip_burst: u32,
+    /// Sustained requests per minute allowed from an IP address.
+    pub ip_per_minute: u32,
+    /// `/connect_client` requests a `client_id` can send at once.
+    pub client_id_burst: u32,
+    /// Sustained `/connect_client` requests per minute allowed for a `client_id`.
+    pub client_id_per_minute: u32,
+    /// Wrong secrets after which a `client_id` is locked out.
+    pub max_failed_logins: u32,
+    /// How long a `client_id` is locked out for, each wrong secret is also remembered this long.
+    pub lockout_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            ip_burst: 20,
+            ip_per_minute: 30,
+            client_id_burst: 5,
+            client_id_per_minute: 10,
+            max_failed_logins: 5,
+            lockout_secs: 300,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), SERVER_PORT),
+            public_addr: SERVER_ADDR,
+            auth_bind_addr: AUTH_BACKEND_ADDRESS,
+            tick_rate_hz: FIXED_TIMESTEP_HZ,
+            send_interval_ms: SEND_INTERVAL.as_millis() as u64,
+            protocol_id: SHARED_SETTINGS.protocol_id,
+            certificate: WebTransportCertificateSettings::FromFile {
+                cert: "../../certificates/cert.pem".to_string(),
+                key: "../../certificates/key.pem".to_string(),
+            },
+            private_key_path: "private.key".into(),
+            private_key: None,
+            allow_insecure_key: false,
+            accounts_path: "accounts.json".into(),
+            map: DEFAULT_MAP.to_string(),
+            interest_line_of_sight: false,
+            reconnect_grace_period_secs: 30,
+            token_expire_secs: 30,
+            token_timeout_secs: 15,
+            rate_limit: RateLimitConfig::default(),
+            input_validation: InputValidationConfig::default(),
+            player_class: PlayerClass::default(),
+            movement: MovementSettings::default(),
+        }
+    }
+}
+
+/// Sanity checks on the inputs of clients, see [`crate::input_validation`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputValidationConfig {
+    pub enabled: bool,
+    /// Inputs and movements are checked over windows of this many seconds.
+    pub window_secs: f32,
+    /// Max ratio between the ticks a client sent inputs for and the ticks the server simulated,
+    /// over a window. Above 1 to tolerate clients catching up after a lag spike.
+    pub max_input_rate: f32,
+    /// Max time the inputs of a client can be ahead of the server.
+    pub max_input_lead_ms: u64,
+    /// Max ratio between the distance a player moved over a window and what its max speed allows.
+    pub max_speed_ratio: f32,
+    /// Disconnect clients after this many violations, 0 to only log and flag them.
+    pub disconnect_after: u32,
+}
+
+impl Default for InputValidationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_secs: 5.0,
+            max_input_rate: 1.2,
+            max_input_lead_ms: 1000,
+            max_speed_ratio: 1.25,
+            disconnect_after: 0,
+        }
+    }
+}
+
+/// Command line flags, each of them can also be set through an environment variable.
+#[derive(Parser, Debug)]
+#[command(about = "Game server and authentication backend")]
+struct Cli {
+    /// Path to the TOML config file [default: server.toml, if it exists]
+    #[arg(long, short, env = "SERVER_CONFIG")]
+    config: Option<PathBuf>,
+    /// Address the game server listens on
+    #[arg(long, env = "SERVER_BIND_ADDR")]
+    bind_addr: Option<SocketAddr>,
+    /// Address of the game server as reachable by clients
+    #[arg(long, env = "SERVER_PUBLIC_ADDR")]
+    public_addr: Option<SocketAddr>,
+    /// Address the authentication backend listens on
+    #[arg(long, env = "SERVER_AUTH_BIND_ADDR")]
+    auth_bind_addr: Option<SocketAddr>,
+    /// Simulation ticks per second
+    #[arg(long, env = "SERVER_TICK_RATE_HZ")]
+    tick_rate_hz: Option<f64>,
+    /// Interval between replication updates, in milliseconds
+    #[arg(long, env = "SERVER_SEND_INTERVAL_MS")]
+    send_interval_ms: Option<u64>,
+    #[arg(long, env = "SERVER_PROTOCOL_ID")]
+    protocol_id: Option<u64>,
+    /// Generate a self-signed certificate instead of loading one from disk
+    #[arg(long, env = "SERVER_SELF_SIGNED", conflicts_with = "cert")]
+    self_signed: bool,
+    /// Path to the certificate .pem file
+    #[arg(long, env = "SERVER_CERT", requires = "cert_key")]
+    cert: Option<String>,
+    /// Path to the certificate private key .pem file
+    #[arg(long, env = "SERVER_CERT_KEY", requires = "cert")]
+    cert_key: Option<String>,
+    /// Path to the key used to sign connect tokens
+    #[arg(long, env = "SERVER_PRIVATE_KEY_PATH")]
+    private_key_path: Option<PathBuf>,
+    /// Hex encoded key used to sign connect tokens, instead of reading it from a file
+    #[arg(long, env = "SERVER_PRIVATE_KEY", hide_env_values = true, value_parser = parse_hex_key)]
+    private_key: Option<Key>,
+    /// Start with a missing or zero private key. Anyone can then forge connect tokens: dev only!
+    #[arg(long, env = "SERVER_ALLOW_INSECURE_KEY")]
+    allow_insecure_key: bool,
+    /// Path to the accounts file
+    #[arg(long, env = "SERVER_ACCOUNTS_PATH")]
+    accounts_path: Option<PathBuf>,
+    /// Map to play, eg: maps/arena.map.ron
+    #[arg(long, env = "SERVER_MAP")]
+    map: Option<String>,
+    /// Hide entities behind walls from interest management
+    #[arg(long, env = "SERVER_INTEREST_LINE_OF_SIGHT")]
+    interest_line_of_sight: bool,
+    /// Seconds a disconnected player is kept for its client to reconnect, 0 to despawn it immediately
+    #[arg(long, env = "SERVER_RECONNECT_GRACE_PERIOD_SECS")]
+    reconnect_grace_period_secs: Option<u64>,
+    /// Seconds a connect token is valid for
+    #[arg(long, env = "SERVER_TOKEN_EXPIRE_SECS")]
+    token_expire_secs: Option<i32>,
+    /// Seconds without packets before a connection times out, negative to never time out
+    #[arg(long, env = "SERVER_TOKEN_TIMEOUT_SECS", allow_negative_numbers = true)]
+    token_timeout_secs: Option<i32>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, error } => {
+                write!(f, "could not read config file {}: {error}", path.display())
+            }
+            ConfigError::Parse { path, error } => {
+                write!(f, "could not parse config file {}: {error}", path.display())
+            }
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Parses a key written as 64 hex characters, as printed by `generate_auth_private_key`.
+fn parse_hex_key(hex: &str) -> Result<Key, String> {
+    let hex = hex.trim();
+    let mut key = Key::default();
+    if hex.len() != key.len() * 2 {
+        return Err(format!(
+            "expected {} hex characters, got {}",
+            key.len() * 2,
+            hex.len()
+        ));
+    }
+    for (byte, chunk) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let chunk = core::str::from_utf8(chunk).map_err(|e| e.to_string())?;
+        *byte = u8::from_str_radix(chunk, 16).map_err(|e| format!("invalid hex {chunk:?}: {e}"))?;
+    }
+    Ok(key)
+}
+
+impl ServerConfig {
+    /// Builds the config from the process arguments, environment and config file, then validates it.
+    pub fn load() -> Result<Self, ConfigError> {
+        let cli = Cli::parse();
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+                if path.exists() {
+                    Self::from_file(&path)?
+                } else {
+                    Self::default()
+                }
+            }
+        };
+        config.apply_cli(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|error| ConfigError::Read {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        toml::from_str(&content).map_err(|error| ConfigError::Parse {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    fn apply_cli(&mut self, cli: Cli) {
+        if let Some(bind_addr) = cli.bind_addr {
+            self.bind_addr = bind_addr;
+        }
+        if let Some(public_addr) = cli.public_addr {
+            self.public_addr = public_addr;
+        }
+        if let Some(auth_bind_addr) = cli.auth_bind_addr {
+            self.auth_bind_addr = auth_bind_addr;
+        }
+        if let Some(tick_rate_hz) = cli.tick_rate_hz {
+            self.tick_rate_hz = tick_rate_hz;
+        }
+        if let Some(send_interval_ms) = cli.send_interval_ms {
+            self.send_interval_ms = send_interval_ms;
+        }
+        if let Some(protocol_id) = cli.protocol_id {
+            self.protocol_id = protocol_id;
+        }
+        if cli.self_signed {
+            self.certificate = WebTransportCertificateSettings::default();
+        }
+        if let (Some(cert), Some(key)) = (cli.cert, cli.cert_key) {
+            self.certificate = WebTransportCertificateSettings::FromFile { cert, key };
+        }
+        if let Some(private_key_path) = cli.private_key_path {
+            self.private_key_path = private_key_path;
+        }
+        if let Some(private_key) = cli.private_key {
+            self.private_key = Some(private_key);
+        }
+        if cli.allow_insecure_key {
+            self.allow_insecure_key = true;
+        }
+        if let Some(accounts_path) = cli.accounts_path {
+            self.accounts_path = accounts_path;
+        }
+        if let Some(map) = cli.map {
+            self.map = map;
+        }
+        if cli.interest_line_of_sight {
+            self.interest_line_of_sight = true;
+        }
+        if let Some(reconnect_grace_period_secs) = cli.reconnect_grace_period_secs {
+            self.reconnect_grace_period_secs = reconnect_grace_period_secs;
+        }
+        if let Some(token_expire_secs) = cli.token_expire_secs {
+            self.token_expire_secs = token_expire_secs;
+        }
+        if let Some(token_timeout_secs) = cli.token_timeout_secs {
+            self.token_timeout_secs = token_timeout_secs;
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !self.tick_rate_hz.is_finite() || self.tick_rate_hz <= 0.0 {
+            return Err(ConfigError::Invalid(format!(
+                "tick_rate_hz must be a positive number, got {}",
+                self.tick_rate_hz
+            )));
+        }
+        if self.send_interval_ms == 0 {
+            return Err(ConfigError::Invalid(
+                "send_interval_ms must be greater than 0".to_string(),
+            ));
+        }
+        if self.public_addr.ip().is_unspecified() || self.public_addr.port() == 0 {
+            return Err(ConfigError::Invalid(format!(
+                "public_addr must be an address clients can connect to, got {}",
+                self.public_addr
+            )));
+        }
+        if let WebTransportCertificateSettings::FromFile { cert, key } = &self.certificate {
+            for path in [cert, key] {
+                if !Path::new(path).is_file() {
+                    return Err(ConfigError::Invalid(format!(
+                        "certificate file {path} does not exist, \
+                         generate it with `cargo run --bin generate_cert_self_signed` or use --self-signed"
+                    )));
+                }
+            }
+        }
+        if self.token_expire_secs <= 0 {
+            return Err(ConfigError::Invalid(format!(
+                "token_expire_secs must be greater than 0, got {}",
+                self.token_expire_secs
+            )));
+        }
+        if self.token_timeout_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "token_timeout_secs must not be 0, use a negative value to disable timeouts"
+                    .to_string(),
+            ));
+        }
+        let rate_limit = &self.rate_limit;
+        for (name, value) in [
+            ("ip_burst", rate_limit.ip_burst),
+            ("ip_per_minute", rate_limit.ip_per_minute),
+            ("client_id_burst", rate_limit.client_id_burst),
+            ("client_id_per_minute", rate_limit.client_id_per_minute),
+            ("max_failed_logins", rate_limit.max_failed_logins),
+        ] {
+            if value == 0 {
+                return Err(ConfigError::Invalid(format!(
+                    "rate_limit.{name} must be greater than 0"
+                )));
+            }
+        }
+        // a lockout of 0 seconds would never lock anyone out
+        if rate_limit.lockout_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "rate_limit.lockout_secs must be greater than 0".to_string(),
+            ));
+        }
+        let input_validation = &self.input_validation;
+        if !input_validation.window_secs.is_finite() || input_validation.window_secs <= 0.0 {
+            return Err(ConfigError::Invalid(format!(
+                "input_validation.window_secs must be a positive number, got {}",
+                input_validation.window_secs
+            )));
+        }
+        // tick differences wrap around past i16::MAX, the window would never end
+        let window_ticks = f64::from(input_validation.window_secs) * self.tick_rate_hz;
+        if window_ticks > f64::from(i16::MAX) {
+            return Err(ConfigError::Invalid(format!(
+                "input_validation.window_secs must be at most {:.0} at {} ticks per second, got {}",
+                f64::from(i16::MAX) / self.tick_rate_hz,
+                self.tick_rate_hz,
+                input_validation.window_secs
+            )));
+        }
+        for (name, value) in [
+            ("max_input_rate", input_validation.max_input_rate),
+            ("max_speed_ratio", input_validation.max_speed_ratio),
+        ] {
+            if value.is_nan() || value < 1.0 {
+                return Err(ConfigError::Invalid(format!(
+                    "input_validation.{name} must be at least 1, got {value}"
+                )));
+            }
+        }
+        self.movement
+            .validate()
+            .map_err(|e| ConfigError::Invalid(format!("movement.{e}")))?;
+        if !Path::new(ASSETS_PATH).join(&self.map).is_file() {
+            return Err(ConfigError::Invalid(format!(
+                "map {} does not exist in the assets folder {ASSETS_PATH}",
+                self.map
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.tick_rate_hz)
+    }
+
+    pub fn send_interval(&self) -> Duration {
+        Duration::from_millis(self.send_interval_ms)
+    }
+
+    pub fn reconnect_grace_period(&self) -> Duration {
+        Duration::from_secs(self.reconnect_grace_period_secs)
+    }
+
+    pub fn shared_settings(&self) -> SharedSettings {
+        SharedSettings {
+            protocol_id: self.protocol_id,
+        }
+    }
+}
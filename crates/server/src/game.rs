@@ -1,34 +1,49 @@
-use avian2d::prelude::{LinearVelocity, Position, Rotation, SweptCcd};
+use avian2d::prelude::{CollisionStart, LinearVelocity, Position, Rotation, SweptCcd};
 use bevy::prelude::*;
+use core::time::Duration;
 use leafwing_input_manager::prelude::ActionState;
-use lightyear::connection::client::PeerMetadata;
 use lightyear::prelude::*;
+use rand::seq::IndexedRandom;
+use shared::color_from_id;
+use shared::game::map::{LoadedMap, SpawnPoints};
+use shared::game::movement::{MovementSettings, PlayerClass, shared_movement_behaviour};
+use shared::game::projectile::{PROJECTILE_DAMAGE, ProjectileLifetime};
+use shared::game::{Frozen, Wall};
 use shared::protocol::physics::PhysicsBundle;
 use shared::protocol::*;
-use shared::settings::SEND_INTERVAL;
-use shared::{color_from_id, shared_movement_behaviour};
+
+use crate::auth::ClientAccount;
+use crate::config::ServerConfig;
+use crate::input_validation::InputValidationPlugin;
+use crate::interest::{InterestPlugin, InterestRadius, InterestSet};
 
 const OBSTACLE_GAP: f32 = 50.0;
 const OBSTACLES_ROW_COL: i32 = 50;
-const INTEREST_RADIUS: f32 = 150.0;
+const PLAYER_MAX_HEALTH: u16 = 100;
+const RESPAWN_DELAY: Duration = Duration::from_secs(3);
 
 // Plugin for server-specific logic
 pub struct GameServerPlugin;
 
 impl Plugin for GameServerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(RoomPlugin);
-        app.add_systems(Startup, init);
+        app.init_resource::<ServerConfig>();
+        app.add_systems(Startup, (init, init_movement_settings));
 
         // the physics/FixedUpdates systems that consume inputs should be run in this set
         app.add_systems(FixedUpdate, movement);
+        app.add_systems(FixedUpdate, (expire_projectiles, respawn_players));
+        app.add_observer(freeze_disconnected_player);
+        app.add_observer(rebind_reconnected_player);
+        app.add_systems(Update, despawn_abandoned_players);
+        app.add_observer(replicate_projectile);
+        app.add_observer(handle_projectile_hit);
         // messages are not subject to a particular schedule
         app.add_systems(Update, handle_join_game);
         app.add_observer(handle_new_client);
-        app.add_systems(Update, interest_management);
-
-        // Spawn a room for the player entities
-        app.world_mut().spawn(Room::default());
+        app.add_systems(Update, (announce_map, announce_movement_settings));
+        app.add_plugins(InterestPlugin);
+        app.add_plugins(InputValidationPlugin);
     }
 }
 
@@ -37,16 +52,141 @@ impl Plugin for GameServerPlugin {
 ///
 /// You can add additional components to update the connection. In this case we will add a `ReplicationSender` that
 /// will enable us to replicate local entities to that client.
-pub(crate) fn handle_new_client(trigger: On<Add, LinkOf>, mut commands: Commands) {
+pub(crate) fn handle_new_client(
+    trigger: On<Add, LinkOf>,
+    config: Res<ServerConfig>,
+    mut commands: Commands,
+) {
     commands
         .entity(trigger.entity)
         .insert(ReplicationSender::new(
-            SEND_INTERVAL,
+            config.send_interval(),
             SendUpdatesMode::SinceLastAck,
             false,
         ));
 }
 
+/// Tell clients which map is played: on connection, and to everyone when the map changes.
+///
+/// Clients only send [`JoinGame`] once they loaded the same map.
+pub(crate) fn announce_map(
+    loaded_map: Option<Res<LoadedMap>>,
+    mut clients: Query<(Ref<Connected>, &mut MessageSender<MapInfo>)>,
+) {
+    // a map is loading, it will be announced once loaded
+    let Some(loaded_map) = loaded_map else {
+        return;
+    };
+    for (connected, mut sender) in clients.iter_mut() {
+        if loaded_map.is_changed() || connected.is_added() {
+            sender.send::<ChannelPreGame>(loaded_map.0.clone());
+        }
+    }
+}
+
+/// Send the movement settings to clients: on connection, and to everyone when they change.
+///
+/// Clients need them to predict the movement of their player like the server simulates it.
+pub(crate) fn announce_movement_settings(
+    settings: Res<MovementSettings>,
+    mut clients: Query<(Ref<Connected>, &mut MessageSender<MovementSettings>)>,
+) {
+    for (connected, mut sender) in clients.iter_mut() {
+        if settings.is_changed() || connected.is_added() {
+            sender.send::<ChannelPreGame>(settings.clone());
+        }
+    }
+}
+
+/// The player spawned for a client, on the client's connection entity.
+#[derive(Component, Debug)]
+pub(crate) struct PlayerEntity(pub Entity);
+
+/// Time left for the client of a frozen player to reconnect, before the player is despawned
+#[derive(Component, Debug)]
+pub(crate) struct ReconnectTimer(Timer);
+
+/// Keep the player of a disconnected client in the world, frozen, in case the client reconnects.
+pub(crate) fn freeze_disconnected_player(
+    trigger: On<Add, Disconnected>,
+    links: Query<(&RemoteId, &PlayerEntity)>,
+    mut players: Query<&mut LinearVelocity>,
+    config: Res<ServerConfig>,
+    mut commands: Commands,
+) {
+    let Ok((client_id, player)) = links.get(trigger.entity) else {
+        return;
+    };
+    let grace_period = config.reconnect_grace_period();
+    if grace_period.is_zero() {
+        commands.entity(player.0).try_despawn();
+        return;
+    }
+    info!(
+        "Keeping player {:?} of client {:?} for {grace_period:?}",
+        player.0, client_id.0
+    );
+    if let Ok(mut velocity) = players.get_mut(player.0) {
+        *velocity = LinearVelocity::ZERO;
+    }
+    commands.entity(player.0).insert((
+        Frozen,
+        ReconnectTimer(Timer::new(grace_period, TimerMode::Once)),
+    ));
+}
+
+/// When a client reconnects with the same `client_id` during the grace period, give it back its player.
+pub(crate) fn rebind_reconnected_player(
+    trigger: On<Add, Connected>,
+    links: Query<&RemoteId, With<ClientOf>>,
+    mut players: Query<(Entity, &PlayerId, &mut Position, &mut InterestSet), With<ReconnectTimer>>,
+    mut commands: Commands,
+) {
+    let Ok(client_id) = links.get(trigger.entity) else {
+        return;
+    };
+    let Some((player_entity, _, mut position, mut interest_set)) = players
+        .iter_mut()
+        .find(|(_, player_id, ..)| player_id.0 == client_id.0)
+    else {
+        return;
+    };
+    info!(
+        "Client {:?} reconnected to its player {player_entity:?}",
+        client_id.0
+    );
+    // visibility was tracked for the previous connection, recompute it for the new one
+    *interest_set = InterestSet::default();
+    position.set_changed();
+    commands
+        .entity(player_entity)
+        .remove::<(Frozen, ReconnectTimer)>()
+        .insert(ControlledBy {
+            owner: trigger.entity,
+            lifetime: Lifetime::Persistent,
+        });
+    commands
+        .entity(trigger.entity)
+        .insert(PlayerEntity(player_entity));
+}
+
+/// Despawn the players whose client didn't reconnect in time
+pub(crate) fn despawn_abandoned_players(
+    time: Res<Time>,
+    mut players: Query<(Entity, &PlayerId, &mut ReconnectTimer)>,
+    mut commands: Commands,
+) {
+    for (entity, player_id, mut timer) in players.iter_mut() {
+        if timer.0.tick(time.delta()).is_finished() {
+            info!(
+                "Client {:?} didn't reconnect, despawning its player",
+                player_id.0
+            );
+            commands.entity(entity).try_despawn();
+        }
+    }
+}
+
 /// If the new client connects to the server, we want to spawn a new player entity for it.
 ///
 /// We can't react before `Connected` because there is no guarantee that the connection request we
@@ -55,28 +195,48 @@ pub(crate) fn handle_new_client(trigger: On<Add, LinkOf>, mut commands: Commands
 ///
 /// We're reading a specific [`JoinGame`] message to allow specific tuning of gameplay (choose type of player...)
 /// FIXME: this may have to be back to [`On<Add, Connected>`], as player gameplay type should be known before and not changeable here.
+///
+/// A client only gets one player: further [`JoinGame`] messages from the same connection are ignored.
 pub(crate) fn handle_join_game(
-    receiver: Query<(Entity, &RemoteId, &mut MessageReceiver<JoinGame>)>,
-    room: Single<Entity, With<Room>>,
+    receiver: Query<(
+        Entity,
+        &RemoteId,
+        &mut MessageReceiver<JoinGame>,
+        Has<PlayerEntity>,
+        Option<&ClientAccount>,
+    )>,
+    spawn_points: Res<SpawnPoints>,
+    config: Res<ServerConfig>,
     mut commands: Commands,
 ) {
-    for (e, client_id, mut message) in receiver {
+    for (e, client_id, mut message, mut joined, account) in receiver {
         let client_id = client_id.0;
         message.receive().for_each(|_message| {
+            if joined {
+                warn!("Client {client_id:?} sent JoinGame but already has a player, ignoring");
+                return;
+            }
+            joined = true;
             let color = color_from_id(client_id);
             let player_entity = commands
                 .spawn((
                     PlayerId(client_id),
-                    Position(Vec2::ZERO),
+                    config.player_class,
+                    Position(random_spawn_point(&spawn_points)),
                     Rotation::default(),
                     LinearVelocity::ZERO,
                     ColorComponent(color),
+                    Weapon::default(),
+                    Health::new(PLAYER_MAX_HEALTH),
+                    InterestSet::default(),
+                    InterestRadius::PLAYER,
                     Replicate::to_clients(NetworkTarget::All),
                     PredictionTarget::to_clients(NetworkTarget::Single(client_id)),
                     InterpolationTarget::to_clients(NetworkTarget::AllExceptSingle(client_id)),
                     ControlledBy {
                         owner: e,
-                        lifetime: Default::default(),
+                        // the player is kept when the client disconnects, see `freeze_disconnected_player`
+                        lifetime: Lifetime::Persistent,
                     },
                     PhysicsBundle::player(),
                     SweptCcd::default(),
@@ -84,28 +244,24 @@ pub(crate) fn handle_join_game(
                     NetworkVisibility,
                 ))
                 .id();
+            if let Some(account) = account {
+                commands
+                    .entity(player_entity)
+                    .insert((Name::new(account.0.display_name.clone()), account.clone()));
+            }
             info!(
                 "Create entity {:?} for client {:?}",
                 player_entity, client_id
             );
-
-            let room = room.entity();
-            // we can control the player visibility in a more static manner by using rooms
-            // we add all clients to a room, as well as all player entities
-            // this means that all clients will be able to see all player entities
-            commands.trigger(RoomEvent {
-                target: RoomTarget::AddSender(e),
-                room,
-            });
-            commands.trigger(RoomEvent {
-                target: RoomTarget::AddEntity(player_entity),
-                room,
-            });
-            // TODO: avoid multiple spawns
+            commands.entity(e).insert(PlayerEntity(player_entity));
         })
     }
 }
 
+fn init_movement_settings(config: Res<ServerConfig>, mut commands: Commands) {
+    commands.insert_resource(config.movement.clone());
+}
+
 pub(crate) fn init(mut commands: Commands) {
     // spawn dots in a grid
     for x in -OBSTACLES_ROW_COL..OBSTACLES_ROW_COL {
@@ -113,6 +269,7 @@ pub(crate) fn init(mut commands: Commands) {
             commands.spawn((
                 Position(Vec2::new(x as f32 * OBSTACLE_GAP, y as f32 * OBSTACLE_GAP)),
                 CircleMarker,
+                InterestRadius::CIRCLE,
                 Replicate::to_clients(NetworkTarget::All),
                 NetworkVisibility,
             ));
@@ -120,54 +277,144 @@ pub(crate) fn init(mut commands: Commands) {
     }
 }
 
-/// Here we perform more "immediate" interest management: we will make a circle visible to a client
-/// depending on the distance to the client's entity
-pub(crate) fn interest_management(
-    peer_metadata: Res<PeerMetadata>,
-    player_query: Query<(&PlayerId, Ref<Position>), (Without<CircleMarker>, With<Replicate>)>,
-    mut circle_query: Query<
-        (Entity, &Position, &mut ReplicationState),
-        (With<CircleMarker>, With<Replicate>, With<NetworkVisibility>),
-    >,
+/// Projectiles are spawned by the shared `fire_projectiles` system, we replicate them from the server:
+/// - the shooter predicts them (they were prespawned on its client)
+/// - other clients interpolate them, if interest management makes them visible
+pub(crate) fn replicate_projectile(
+    trigger: On<Add, Projectile>,
+    projectiles: Query<&Projectile>,
+    mut commands: Commands,
+) {
+    let Ok(projectile) = projectiles.get(trigger.entity) else {
+        return;
+    };
+    let owner = projectile.owner;
+    commands.entity(trigger.entity).insert((
+        Replicate::to_clients(NetworkTarget::All),
+        PredictionTarget::to_clients(NetworkTarget::Single(owner)),
+        InterpolationTarget::to_clients(NetworkTarget::AllExceptSingle(owner)),
+        InterestRadius::PROJECTILE,
+        NetworkVisibility,
+    ));
+}
+
+/// Despawn projectiles that didn't hit anything for too long
+pub(crate) fn expire_projectiles(
+    mut commands: Commands,
+    mut projectiles: Query<(Entity, &mut ProjectileLifetime)>,
+) {
+    for (entity, mut lifetime) in projectiles.iter_mut() {
+        lifetime.0 = lifetime.0.saturating_sub(1);
+        if lifetime.0 == 0 {
+            commands.entity(entity).try_despawn();
+        }
+    }
+}
+
+/// Time left before a dead player respawns
+#[derive(Component, Debug)]
+pub(crate) struct RespawnTimer(Timer);
+
+impl Default for RespawnTimer {
+    fn default() -> Self {
+        Self(Timer::new(RESPAWN_DELAY, TimerMode::Once))
+    }
+}
+
+/// Despawn projectiles when they hit a wall or a player, and damage the player.
+pub(crate) fn handle_projectile_hit(
+    trigger: On<CollisionStart>,
+    mut projectiles: Query<(&Projectile, &mut ProjectileLifetime)>,
+    walls: Query<(), With<Wall>>,
+    mut players: Query<(&PlayerId, &mut Health, &mut LinearVelocity), Without<Dead>>,
+    mut commands: Commands,
 ) {
-    for (client_id, position) in player_query.iter() {
-        let Some(sender_entity) = peer_metadata.mapping.get(&client_id.0) else {
-            error!("Could not find sender entity for client: {:?}", client_id);
-            return;
+    let collision = trigger.event();
+    for (projectile_entity, other) in [
+        (collision.collider1, collision.collider2),
+        (collision.collider2, collision.collider1),
+    ] {
+        let Ok((projectile, mut lifetime)) = projectiles.get_mut(projectile_entity) else {
+            continue;
         };
-        if position.is_changed() {
-            // in real game, you would have a spatial index (kd-tree) to only find entities within a certain radius
-            for (circle, circle_position, mut state) in circle_query.iter_mut() {
-                let distance = position.distance(**circle_position);
-                if distance < INTEREST_RADIUS {
-                    trace!("Gain visibility with {circle:?}");
-                    state.gain_visibility(*sender_entity);
-                } else {
-                    trace!("Lose visibility with {circle:?}");
-                    state.lose_visibility(*sender_entity);
-                }
+        // the despawn is deferred: a projectile touching several players in the same frame
+        // would hit all of them. Its lifetime is spent right away instead.
+        if lifetime.0 == 0 {
+            continue;
+        }
+        if walls.contains(other) {
+            lifetime.0 = 0;
+            commands.entity(projectile_entity).try_despawn();
+        } else if let Ok((player_id, mut health, mut velocity)) = players.get_mut(other) {
+            // players can't shoot themselves
+            if player_id.0 == projectile.owner {
+                continue;
+            }
+            lifetime.0 = 0;
+            commands.entity(projectile_entity).try_despawn();
+            health.current = health.current.saturating_sub(PROJECTILE_DAMAGE);
+            if health.current == 0 {
+                info!(
+                    "Player {:?} was killed by {:?}",
+                    player_id.0, projectile.owner
+                );
+                *velocity = LinearVelocity::ZERO;
+                commands
+                    .entity(other)
+                    .insert((Dead, RespawnTimer::default()));
             }
         }
     }
 }
 
+/// Bring dead players back to life at a spawn point, once their respawn delay is over
+pub(crate) fn respawn_players(
+    time: Res<Time>,
+    spawn_points: Res<SpawnPoints>,
+    mut players: Query<(Entity, &mut RespawnTimer, &mut Health, &mut Position), With<Dead>>,
+    mut commands: Commands,
+) {
+    for (entity, mut timer, mut health, mut position) in players.iter_mut() {
+        if !timer.0.tick(time.delta()).is_finished() {
+            continue;
+        }
+        health.current = health.max;
+        position.0 = random_spawn_point(&spawn_points);
+        commands.entity(entity).remove::<(Dead, RespawnTimer)>();
+    }
+}
+
+fn random_spawn_point(spawn_points: &SpawnPoints) -> Vec2 {
+    spawn_points
+        .0
+        .choose(&mut rand::rng())
+        .copied()
+        .unwrap_or(Vec2::ZERO)
+}
+
 /// Read client inputs and move players
 /// NOTE: this system can now be run in both client/server!
 pub(crate) fn movement(
     timeline: Res<LocalTimeline>,
-    mut action_query: Query<(
-        Entity,
-        &Position,
-        &mut LinearVelocity,
-        &ActionState<PlayerActions>,
-    )>,
+    time: Res<Time>,
+    settings: Res<MovementSettings>,
+    mut action_query: Query<
+        (
+            Entity,
+            &Position,
+            &mut LinearVelocity,
+            &ActionState<PlayerActions>,
+            &PlayerClass,
+        ),
+        (Without<Dead>, Without<Frozen>),
+    >,
 ) {
     let tick = timeline.tick();
-    for (entity, position, velocity, action) in action_query.iter_mut() {
+    for (entity, position, velocity, action, class) in action_query.iter_mut() {
         //if !action.get_pressed().is_empty() {
         // NOTE: be careful to directly pass Mut<PlayerPosition>
         // getting a mutable reference triggers change detection, unless you use `as_deref_mut()`
-        shared_movement_behaviour(velocity, action);
+        shared_movement_behaviour(velocity, action, settings.get(*class), time.delta_secs());
         trace!(?entity, ?tick, ?position, actions = ?action.get_pressed(), "applying movement to player");
         // }
     }
@@ -0,0 +1,153 @@
+//! Sanity checks on the inputs of clients, to catch speedhacks and modified clients.
+//!
+//! The server simulates players from their inputs, so a client can't move them directly, but it can:
+//! - send inputs for more ticks than elapsed, eg: by running its clock faster
+//! - send inputs far ahead of the server tick
+//!
+//! Both are measured over windows of [`window_secs`](crate::config::InputValidationConfig::window_secs), along with the distance
+//! each player moved: it can't exceed the [`max_speed`](shared::game::movement::MovementConfig::max_speed) of its class over
+//! the window, unless something moved the player outside of the movement code (physics pushes,
+//! teleport bugs, inputs applied more than once).
+//!
+//! Violations are logged and counted in a [`Flagged`] component on the client's connection, which
+//! is disconnected after [`disconnect_after`](crate::config::InputValidationConfig::disconnect_after) violations if set.
+use avian2d::prelude::Position;
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::ActionState;
+use lightyear::connection::client::Disconnecting;
+use lightyear::prelude::input::InputBuffer;
+use lightyear::prelude::*;
+use shared::game::Frozen;
+use shared::game::movement::{MovementSettings, PlayerClass};
+use shared::protocol::{Dead, PlayerActions, PlayerId};
+
+use crate::config::ServerConfig;
+
+pub struct InputValidationPlugin;
+
+impl Plugin for InputValidationPlugin {
+    fn build(&self, app: &mut App) {
+        // after physics, so that positions are up to date
+        app.add_systems(
+            FixedLast,
+            validate_inputs.run_if(|config: Res<ServerConfig>| config.input_validation.enabled),
+        );
+    }
+}
+
+/// A client that sent suspicious inputs. Added on its connection entity.
+#[derive(Component, Debug, Default)]
+pub struct Flagged {
+    pub violations: u32,
+    pub last_reason: String,
+}
+
+/// Where a player and its inputs were at the start of the current window.
+#[derive(Component, Debug)]
+pub(crate) struct InputWindow {
+    server_tick: Tick,
+    /// Latest tick the client sent inputs for, if any arrived yet
+    input_tick: Option<Tick>,
+    position: Vec2,
+}
+
+fn validate_inputs(
+    timeline: Res<LocalTimeline>,
+    config: Res<ServerConfig>,
+    movement: Res<MovementSettings>,
+    mut players: Query<
+        (
+            Entity,
+            &PlayerId,
+            &PlayerClass,
+            &Position,
+            &InputBuffer<ActionState<PlayerActions>>,
+            &ControlledBy,
+            Option<&mut InputWindow>,
+            Has<Dead>,
+            Has<Frozen>,
+        ),
+        With<Replicate>,
+    >,
+    flagged: Query<&Flagged>,
+    mut commands: Commands,
+) {
+    let limits = &config.input_validation;
+    let server_tick = timeline.tick();
+    let window_ticks = (limits.window_secs * config.tick_rate_hz as f32).ceil() as i32;
+    let tick_secs = config.tick_duration().as_secs_f32();
+    let max_lead_ticks = (limits.max_input_lead_ms as f32 / 1000.0 / tick_secs) as i32;
+
+    for (entity, player_id, class, position, input_buffer, controlled_by, window, dead, frozen) in
+        players.iter_mut()
+    {
+        let input_tick = input_buffer.end_tick();
+        let new_window = InputWindow {
+            server_tick,
+            input_tick,
+            position: position.0,
+        };
+        // respawns teleport players, and frozen players don't send inputs: start over afterwards
+        if dead || frozen {
+            if window.is_some() {
+                commands.entity(entity).remove::<InputWindow>();
+            }
+            continue;
+        }
+        let Some(mut window) = window else {
+            commands.entity(entity).insert(new_window);
+            continue;
+        };
+        if window.input_tick.is_none() {
+            window.input_tick = input_tick;
+        }
+        let elapsed = i32::from(server_tick - window.server_tick);
+        if elapsed < window_ticks {
+            continue;
+        }
+
+        let mut violations = Vec::new();
+        if let (Some(start), Some(end)) = (window.input_tick, input_tick) {
+            let input_ticks = i32::from(end - start);
+            if input_ticks as f32 > elapsed as f32 * limits.max_input_rate {
+                violations.push(format!(
+                    "sent inputs for {input_ticks} ticks in {elapsed} ticks"
+                ));
+            }
+            let lead = i32::from(end - server_tick);
+            if lead > max_lead_ticks {
+                violations.push(format!("inputs are {lead} ticks ahead of the server"));
+            }
+        }
+        let distance = position.0.distance(window.position);
+        let max_speed = movement.get(*class).max_speed;
+        let max_distance = max_speed * elapsed as f32 * tick_secs * limits.max_speed_ratio;
+        if distance > max_distance {
+            violations.push(format!(
+                "moved {distance:.0} in {elapsed} ticks, at most {max_distance:.0} is possible"
+            ));
+        }
+        *window = new_window;
+
+        let Some(last_reason) = violations.last().cloned() else {
+            continue;
+        };
+        for reason in &violations {
+            warn!(client = ?player_id.0, "Suspicious inputs: {reason}");
+        }
+        let owner = controlled_by.owner;
+        let previous = flagged.get(owner).map_or(0, |flagged| flagged.violations);
+        let total = previous + violations.len() as u32;
+        commands.entity(owner).insert(Flagged {
+            violations: total,
+            last_reason,
+        });
+        if limits.disconnect_after > 0
+            && previous < limits.disconnect_after
+            && total >= limits.disconnect_after
+        {
+            warn!(client = ?player_id.0, "Disconnecting after {total} input violations");
+            commands.entity(owner).insert(Disconnecting);
+        }
+    }
+}
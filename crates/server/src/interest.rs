@@ -0,0 +1,302 @@
+//! Interest management: only replicate to a client the entities close to its player.
+//!
+//! Entities with an [`InterestRadius`] are indexed in a [`SpatialGrid`], and each player keeps track
+//! of the entities visible to its client in an [`InterestSet`]. Work is only done for what moved:
+//! - when a player moves, its visible set is recomputed from the entities of the nearby grid cells
+//! - when an entity moves, it is checked against each player
+//!
+//! Players and projectiles are subject to it too, except for their own client which always sees
+//! them: a client must not learn where a hidden shooter is from its projectiles.
+//!
+//! With [`ServerConfig::interest_line_of_sight`], entities hidden behind a [`Wall`] are not visible,
+//! so a modified client can't reveal them.
+use avian2d::prelude::{Position, SpatialQuery, SpatialQueryFilter};
+use bevy::ecs::entity::{EntityHashMap, EntityHashSet};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use core::time::Duration;
+use lightyear::connection::client::PeerMetadata;
+use lightyear::prelude::*;
+use shared::game::{Frozen, Wall};
+use shared::protocol::{PlayerId, Projectile};
+use shared::spatial_grid::SpatialGrid;
+
+use crate::config::ServerConfig;
+
+/// Cell size of the [`InterestGrid`], queries are the fastest for radii close to it.
+const GRID_CELL_SIZE: f32 = 150.0;
+
+pub struct InterestPlugin;
+
+impl Plugin for InterestPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InterestGrid>();
+        app.add_observer(remove_from_interest_grid);
+        app.add_systems(Update, (update_interest_grid, interest_management).chain());
+    }
+}
+
+/// Distances at which an entity becomes visible to a player, and stops being visible.
+///
+/// Entities without this component are not subject to interest management.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct InterestRadius {
+    /// The entity becomes visible when closer than this.
+    pub enter: f32,
+    /// The entity stops being visible when further than this.
+    /// Larger than `enter`, so that an entity on the boundary doesn't flicker in and out of replication.
+    pub exit: f32,
+    /// Once visible, the entity stays visible at least this long.
+    pub min_hold: Duration,
+}
+
+impl InterestRadius {
+    pub const CIRCLE: Self = Self {
+        enter: 150.0,
+        exit: 180.0,
+        min_hold: Duration::from_millis(500),
+    };
+
+    /// Players are seen from further away, as they matter more than the decor.
+    pub const PLAYER: Self = Self {
+        enter: 300.0,
+        exit: 350.0,
+        min_hold: Duration::from_secs(1),
+    };
+
+    /// Projectiles are seen from as far as players, they are short lived so they aren't held.
+    pub const PROJECTILE: Self = Self {
+        enter: 300.0,
+        exit: 350.0,
+        min_hold: Duration::ZERO,
+    };
+}
+
+/// Spatial index of the entities subject to interest management
+#[derive(Resource)]
+pub(crate) struct InterestGrid {
+    grid: SpatialGrid,
+    /// Largest exit radius of the indexed entities, it bounds the queries.
+    /// It is never decreased, which only makes queries a bit larger than needed.
+    max_exit_radius: f32,
+}
+
+impl Default for InterestGrid {
+    fn default() -> Self {
+        Self {
+            grid: SpatialGrid::new(GRID_CELL_SIZE),
+            max_exit_radius: 0.0,
+        }
+    }
+}
+
+/// Entities currently visible to the client controlling this player
+#[derive(Component, Debug, Default)]
+pub(crate) struct InterestSet {
+    /// Visible entities, with the time at which they became visible
+    visible: EntityHashMap<Duration>,
+    /// Entities out of their exit radius, only kept visible because of their `min_hold`
+    held: EntityHashSet,
+}
+
+/// A change of visibility of an entity for a player
+enum VisibilityChange {
+    Gain,
+    Lose,
+}
+
+impl InterestSet {
+    fn update(
+        &mut self,
+        entity: Entity,
+        distance: f32,
+        radius: &InterestRadius,
+        now: Duration,
+    ) -> Option<VisibilityChange> {
+        let Some(since) = self.visible.get(&entity) else {
+            if distance < radius.enter {
+                self.visible.insert(entity, now);
+                return Some(VisibilityChange::Gain);
+            }
+            return None;
+        };
+        if distance < radius.exit {
+            self.held.remove(&entity);
+            None
+        } else if now.saturating_sub(*since) < radius.min_hold {
+            self.held.insert(entity);
+            None
+        } else {
+            self.visible.remove(&entity);
+            self.held.remove(&entity);
+            Some(VisibilityChange::Lose)
+        }
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        self.visible.remove(&entity);
+        self.held.remove(&entity);
+    }
+}
+
+fn update_interest_grid(
+    mut interest_grid: ResMut<InterestGrid>,
+    moved: Query<
+        (Entity, &Position, &InterestRadius),
+        Or<(Changed<Position>, Changed<InterestRadius>)>,
+    >,
+) {
+    for (entity, position, radius) in moved.iter() {
+        interest_grid.grid.insert(entity, position.0);
+        interest_grid.max_exit_radius = interest_grid.max_exit_radius.max(radius.exit);
+    }
+}
+
+fn remove_from_interest_grid(
+    trigger: On<Remove, InterestRadius>,
+    mut interest_grid: ResMut<InterestGrid>,
+    mut interest_sets: Query<&mut InterestSet>,
+) {
+    interest_grid.grid.remove(trigger.entity);
+    for mut interest_set in interest_sets.iter_mut() {
+        interest_set.remove(trigger.entity);
+    }
+}
+
+/// Distance used for visibility, infinite if a wall blocks the line of sight.
+#[derive(SystemParam)]
+struct LineOfSight<'w, 's> {
+    config: Res<'w, ServerConfig>,
+    spatial_query: SpatialQuery<'w, 's>,
+    walls: Query<'w, 's, (), With<Wall>>,
+}
+
+impl LineOfSight<'_, '_> {
+    fn distance(&self, from: Vec2, to: Vec2, radius: &InterestRadius) -> f32 {
+        let distance = from.distance(to);
+        // out of range anyway, skip the raycast
+        if !self.config.interest_line_of_sight || distance >= radius.exit {
+            return distance;
+        }
+        let Ok(direction) = Dir2::new(to - from) else {
+            return distance;
+        };
+        let blocked = self
+            .spatial_query
+            .cast_ray_predicate(
+                from,
+                direction,
+                distance,
+                true,
+                &SpatialQueryFilter::default(),
+                &|entity| self.walls.contains(entity),
+            )
+            .is_some();
+        if blocked { f32::INFINITY } else { distance }
+    }
+}
+
+/// Here we perform more "immediate" interest management: we will make an entity visible to a client
+/// depending on the distance to the client's entity
+fn interest_management(
+    time: Res<Time>,
+    peer_metadata: Res<PeerMetadata>,
+    interest_grid: Res<InterestGrid>,
+    // frozen players have no connected client to replicate to
+    mut player_query: Query<
+        (Entity, &PlayerId, Ref<Position>, &mut InterestSet),
+        (With<Replicate>, Without<Frozen>),
+    >,
+    moved: Query<(Entity, &Position), (Changed<Position>, With<InterestRadius>)>,
+    radii: Query<&InterestRadius>,
+    projectiles: Query<&Projectile>,
+    line_of_sight: LineOfSight,
+    mut states: Query<&mut ReplicationState, With<NetworkVisibility>>,
+) {
+    let now = time.elapsed();
+    for (player_entity, client_id, position, mut interest_set) in player_query.iter_mut() {
+        let Some(sender_entity) = peer_metadata.mapping.get(&client_id.0) else {
+            error!("Could not find sender entity for client: {:?}", client_id);
+            continue;
+        };
+        let distance_to = |entity: Entity, entity_position: Vec2, radius: &InterestRadius| {
+            // the client always sees its own player and projectiles
+            let owned = projectiles
+                .get(entity)
+                .is_ok_and(|projectile| projectile.owner == client_id.0);
+            if entity == player_entity || owned {
+                0.0
+            } else {
+                line_of_sight.distance(position.0, entity_position, radius)
+            }
+        };
+        let mut changes = Vec::new();
+        if position.is_changed() {
+            let mut in_range = EntityHashSet::default();
+            for (entity, entity_position) in interest_grid
+                .grid
+                .within_radius(position.0, interest_grid.max_exit_radius)
+            {
+                in_range.insert(entity);
+                let Ok(radius) = radii.get(entity) else {
+                    continue;
+                };
+                let distance = distance_to(entity, entity_position, radius);
+                changes.extend(
+                    interest_set
+                        .update(entity, distance, radius, now)
+                        .map(|change| (entity, change)),
+                );
+            }
+            // visible entities out of the query range are out of their exit radius
+            let out_of_range: Vec<Entity> = interest_set
+                .visible
+                .keys()
+                .filter(|entity| !in_range.contains(*entity))
+                .copied()
+                .collect();
+            for entity in out_of_range {
+                let Ok(radius) = radii.get(entity) else {
+                    continue;
+                };
+                changes.extend(
+                    interest_set
+                        .update(entity, f32::INFINITY, radius, now)
+                        .map(|change| (entity, change)),
+                );
+            }
+        } else {
+            let held: Vec<Entity> = interest_set.held.iter().copied().collect();
+            let moved_entities = moved.iter().map(|(entity, position)| (entity, position.0));
+            let held_entities = held
+                .into_iter()
+                .filter_map(|entity| Some((entity, interest_grid.grid.position(entity)?)));
+            for (entity, entity_position) in moved_entities.chain(held_entities) {
+                let Ok(radius) = radii.get(entity) else {
+                    continue;
+                };
+                let distance = distance_to(entity, entity_position, radius);
+                changes.extend(
+                    interest_set
+                        .update(entity, distance, radius, now)
+                        .map(|change| (entity, change)),
+                );
+            }
+        }
+        for (entity, change) in changes {
+            let Ok(mut state) = states.get_mut(entity) else {
+                continue;
+            };
+            match change {
+                VisibilityChange::Gain => {
+                    trace!("Gain visibility with {entity:?}");
+                    state.gain_visibility(*sender_entity);
+                }
+                VisibilityChange::Lose => {
+                    trace!("Lose visibility with {entity:?}");
+                    state.lose_visibility(*sender_entity);
+                }
+            }
+        }
+    }
+}
@@ -8,44 +8,93 @@
 //! - `cargo run -- server`
 //! - `cargo run -- client -c 1`
 
+mod accounts;
 mod auth;
 mod certificate;
 mod common_server;
+mod config;
 mod game;
+mod input_validation;
+mod interest;
+mod rate_limit;
+#[cfg(test)]
+mod tests;
 
+extern crate alloc;
+use alloc::sync::Arc;
 use bevy::diagnostic::DiagnosticsPlugin;
 use bevy::log::LogPlugin;
 use bevy::prelude::*;
 use bevy::state::app::StatesPlugin;
-use core::time::Duration;
 use game::GameServerPlugin;
+use lightyear::prelude::Identity;
 use lightyear::prelude::server::ServerPlugins;
 use shared::SharedPlugin;
-use shared::auth::AUTH_BACKEND_ADDRESS;
-use shared::settings::{FIXED_TIMESTEP_HZ, SERVER_ADDR, SHARED_SETTINGS};
+use shared::game::map::MapSelection;
 use tracing::Level;
 
+use crate::accounts::FileAccountStore;
+use crate::certificate::certificate_digest;
 use crate::common_server::*;
+use crate::config::ServerConfig;
 
 /// When running the example as a binary, we only support Client or Server mode.
 fn main() {
+    let config = ServerConfig::load().unwrap_or_else(|e| {
+        eprintln!("Failed to load server config: {e}");
+        std::process::exit(1);
+    });
+    let private_key = auth::load_private_key(&config).unwrap_or_else(|e| {
+        eprintln!("Failed to load private key: {e}");
+        eprintln!("Use --allow-insecure-key to start anyway, for local development only.");
+        std::process::exit(1);
+    });
+
     let mut app = new_headless_app();
+    // needs the IoTaskPool to be initialized, to read the certificate files
+    let identity = Identity::from(&config.certificate);
+    let certificate_digest = certificate_digest(&identity);
+
     app.add_plugins(ServerPlugins {
-        tick_duration: Duration::from_secs_f64(1.0 / FIXED_TIMESTEP_HZ),
+        tick_duration: config.tick_duration(),
     });
 
     app.add_plugins(SharedPlugin);
 
     app.world_mut().spawn(ExampleServer {
-        shared: SHARED_SETTINGS,
+        shared: config.shared_settings(),
+        bind_addr: config.bind_addr,
+        identity,
+        private_key,
     });
     app.add_systems(Startup, start);
 
     app.add_plugins(GameServerPlugin);
+    let accounts = FileAccountStore::open(&config.accounts_path).unwrap_or_else(|e| {
+        eprintln!(
+            "Failed to open accounts store {}: {e}",
+            config.accounts_path.display()
+        );
+        std::process::exit(1);
+    });
+    info!(
+        "Loaded {} accounts from {}",
+        accounts.account_count(),
+        config.accounts_path.display()
+    );
     app.add_plugins(auth::AuthServerPlugin {
-        game_server_addr: SERVER_ADDR,
-        auth_backend_addr: AUTH_BACKEND_ADDRESS,
+        game_server_addr: config.public_addr,
+        auth_backend_addr: config.auth_bind_addr,
+        protocol_id: config.protocol_id,
+        private_key,
+        token_expire_secs: config.token_expire_secs,
+        token_timeout_secs: config.token_timeout_secs,
+        certificate_digest,
+        accounts: Arc::new(accounts),
+        rate_limits: config.rate_limit.clone(),
     });
+    app.insert_resource(MapSelection(config.map.clone()));
+    app.insert_resource(config);
 
     app.run();
 }
@@ -62,7 +111,10 @@ pub fn new_headless_app() -> App {
     let mut app = App::new();
     app.add_plugins((
         MinimalPlugins,
-        bevy::prelude::AssetPlugin::default(),
+        bevy::prelude::AssetPlugin {
+            file_path: shared::settings::ASSETS_PATH.to_string(),
+            ..default()
+        },
         bevy::scene::ScenePlugin,
         log_plugin(),
         StatesPlugin,
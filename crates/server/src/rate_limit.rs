@@ -0,0 +1,259 @@
+//! Limits on the auth backend endpoints, so that nobody can mint unlimited client ids
+//! or brute-force the secret of a known `client_id`:
+//! - requests are rate limited per IP address, by a middleware
+//! - `/connect_client` is also rate limited per `client_id`
+//! - a `client_id` is locked out after too many wrong secrets
+//!
+//! IPv6 clients are limited per /64 prefix, the smallest block usually assigned to a host.
+//!
+//! Limited requests are refused with `429 Too Many Requests`.
+//!
+//! NOTE: behind a reverse proxy, all requests come from the proxy address: limits have to be
+//! enforced by the proxy instead.
+extern crate alloc;
+use alloc::sync::Arc;
+use core::hash::Hash;
+use core::net::{IpAddr, Ipv6Addr, SocketAddr};
+use core::time::Duration;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::auth::AuthError;
+use crate::config::RateLimitConfig;
+
+/// Above this many tracked keys, stale entries are dropped to bound memory usage.
+const MAX_TRACKED_KEYS: usize = 10_000;
+/// Dropping stale entries goes through the whole map, so it's done at most this often.
+const PURGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Token bucket rate limiter: each key can do `burst` requests at once,
+/// then gets a new one every `60 / per_minute` seconds.
+///
+/// At most [`MAX_TRACKED_KEYS`] keys are tracked: when they are all busy, new keys are refused
+/// until some buckets refill.
+pub struct RateLimiter<K> {
+    capacity: f64,
+    refill_per_sec: f64,
+    max_keys: usize,
+    buckets: Mutex<Buckets<K>>,
+}
+
+struct Buckets<K> {
+    buckets: HashMap<K, Bucket>,
+    last_purge: Option<Instant>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(burst: u32, per_minute: u32) -> Self {
+        Self {
+            capacity: f64::from(burst),
+            refill_per_sec: f64::from(per_minute) / 60.0,
+            max_keys: MAX_TRACKED_KEYS,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_purge: None,
+            }),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = max_keys;
+        self
+    }
+
+    /// Takes a token for `key`, or returns how long to wait for the next one.
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    pub(crate) fn check_at(&self, key: K, now: Instant) -> Result<(), Duration> {
+        let mut state = lock(&self.buckets);
+        if state.buckets.len() >= self.max_keys && !state.buckets.contains_key(&key) {
+            if state
+                .last_purge
+                .is_none_or(|last_purge| now.duration_since(last_purge) >= PURGE_INTERVAL)
+            {
+                state.last_purge = Some(now);
+                // a bucket refilled to capacity is the same as no bucket
+                let full_after = self.capacity / self.refill_per_sec;
+                state.buckets.retain(|_, bucket| {
+                    now.duration_since(bucket.updated).as_secs_f64() < full_after
+                });
+            }
+            if state.buckets.len() >= self.max_keys {
+                // refusing new keys rather than evicting busy ones, which would reset their limit
+                return Err(PURGE_INTERVAL);
+            }
+        }
+        let bucket = state.buckets.entry(key).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+}
+
+/// Locks a `client_id` out after `max_failures` wrong secrets, each failure being
+/// remembered for the lockout duration.
+pub struct LoginLockout {
+    max_failures: u32,
+    lockout: Duration,
+    failures: Mutex<TrackedFailures>,
+}
+
+struct TrackedFailures {
+    failures: HashMap<u64, Failures>,
+    last_purge: Option<Instant>,
+}
+
+struct Failures {
+    count: u32,
+    last_failure: Instant,
+}
+
+impl LoginLockout {
+    pub fn new(max_failures: u32, lockout: Duration) -> Self {
+        Self {
+            max_failures,
+            lockout,
+            failures: Mutex::new(TrackedFailures {
+                failures: HashMap::new(),
+                last_purge: None,
+            }),
+        }
+    }
+
+    /// Returns how long the `client_id` is still locked out for, if it is.
+    pub fn check(&self, client_id: u64) -> Result<(), Duration> {
+        self.check_at(client_id, Instant::now())
+    }
+
+    pub(crate) fn check_at(&self, client_id: u64, now: Instant) -> Result<(), Duration> {
+        let mut state = lock(&self.failures);
+        let failures = &mut state.failures;
+        let Some(entry) = failures.get(&client_id) else {
+            return Ok(());
+        };
+        let since_last_failure = now.duration_since(entry.last_failure);
+        if since_last_failure >= self.lockout {
+            failures.remove(&client_id);
+            return Ok(());
+        }
+        if entry.count >= self.max_failures {
+            return Err(self.lockout - since_last_failure);
+        }
+        Ok(())
+    }
+
+    pub fn record_failure(&self, client_id: u64) {
+        self.record_failure_at(client_id, Instant::now());
+    }
+
+    /// Only known `client_id`s get failures, so the map is bounded by the number of accounts:
+    /// expired entries are still dropped once in a while.
+    pub(crate) fn record_failure_at(&self, client_id: u64, now: Instant) {
+        let mut state = lock(&self.failures);
+        if state.failures.len() >= MAX_TRACKED_KEYS
+            && state
+                .last_purge
+                .is_none_or(|last_purge| now.duration_since(last_purge) >= PURGE_INTERVAL)
+        {
+            state.last_purge = Some(now);
+            let lockout = self.lockout;
+            state
+                .failures
+                .retain(|_, entry| now.duration_since(entry.last_failure) < lockout);
+        }
+        let entry = state.failures.entry(client_id).or_insert(Failures {
+            count: 0,
+            last_failure: now,
+        });
+        entry.count += 1;
+        entry.last_failure = now;
+        if entry.count == self.max_failures {
+            bevy::log::warn!(
+                "Client {client_id} locked out for {:?} after {} wrong secrets",
+                self.lockout,
+                entry.count
+            );
+        }
+    }
+
+    pub fn record_success(&self, client_id: u64) {
+        lock(&self.failures).failures.remove(&client_id);
+    }
+}
+
+/// All the limits of the auth backend, shared between the middleware and the handlers.
+pub struct RateLimits {
+    pub per_ip: RateLimiter<IpAddr>,
+    pub per_client_id: RateLimiter<u64>,
+    pub lockout: LoginLockout,
+}
+
+pub type SharedRateLimits = Arc<RateLimits>;
+
+impl RateLimits {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            per_ip: RateLimiter::new(config.ip_burst, config.ip_per_minute),
+            per_client_id: RateLimiter::new(config.client_id_burst, config.client_id_per_minute),
+            lockout: LoginLockout::new(
+                config.max_failed_logins,
+                Duration::from_secs(config.lockout_secs),
+            ),
+        }
+    }
+}
+
+/// Middleware refusing requests from IP addresses that sent too many of them.
+pub async fn limit_by_ip(
+    State(limits): State<SharedRateLimits>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    limits
+        .per_ip
+        .check(ip_key(addr.ip()))
+        .map_err(AuthError::TooManyRequests)?;
+    Ok(next.run(request).await)
+}
+
+/// The key IP addresses are rate limited by: IPv6 addresses by their /64 prefix, as a single
+/// host usually gets a whole /64 and could otherwise pick a new address for every request.
+pub(crate) fn ip_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & !(u128::from(u64::MAX)))),
+        },
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // the maps stay consistent even if a thread panicked while holding the lock
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use crate::accounts::{Account, AccountStore, FileAccountStore};
+
+/// A fresh directory for the store files, removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "server-test-{name}-{}-{}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+#[test]
+fn accounts_survive_reopening() {
+    let dir = TempDir::new("reopen");
+    let path = dir.0.join("accounts.json");
+    let store = FileAccountStore::open(&path).unwrap();
+    assert!(
+        store
+            .insert(1, Account::new(SECRET, "Alice".into()).unwrap())
+            .unwrap()
+    );
+    assert!(
+        store
+            .insert(2, Account::new(SECRET, "Bob".into()).unwrap())
+            .unwrap()
+    );
+    // ids already in use are refused
+    assert!(
+        !store
+            .insert(1, Account::new(SECRET, "Eve".into()).unwrap())
+            .unwrap()
+    );
+    drop(store);
+
+    let store = FileAccountStore::open(&path).unwrap();
+    assert_eq!(store.account_count(), 2);
+    let alice = store.get(1).unwrap();
+    assert_eq!(alice.display_name, "Alice");
+    assert!(alice.verify_secret(SECRET));
+    assert!(
+        store
+            .insert(3, Account::new(SECRET, "Carol".into()).unwrap())
+            .unwrap()
+    );
+    drop(store);
+
+    // the journal was merged into the file on the previous open
+    let store = FileAccountStore::open(&path).unwrap();
+    assert_eq!(store.account_count(), 3);
+    assert_eq!(store.get(2).unwrap().display_name, "Bob");
+}
+
+#[test]
+fn incomplete_journal_line_is_ignored() {
+    let dir = TempDir::new("torn");
+    let path = dir.0.join("accounts.json");
+    let store = FileAccountStore::open(&path).unwrap();
+    assert!(
+        store
+            .insert(1, Account::new(SECRET, "Alice".into()).unwrap())
+            .unwrap()
+    );
+    drop(store);
+    // as if the server crashed while appending an account
+    let journal_path = path.with_extension("json.journal");
+    let mut journal = std::fs::read_to_string(&journal_path).unwrap();
+    journal.push_str("{\"client_id\":2,\"acc");
+    std::fs::write(&journal_path, journal).unwrap();
+
+    let store = FileAccountStore::open(&path).unwrap();
+    assert_eq!(store.account_count(), 1);
+    assert!(store.get(1).is_some());
+}
@@ -0,0 +1,231 @@
+extern crate alloc;
+use alloc::sync::Arc;
+use core::net::{Ipv4Addr, SocketAddr};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::connect_info::MockConnectInfo;
+use axum::http::{Request, StatusCode, header};
+use lightyear::netcode::{ConnectToken, NetcodeServer};
+use lightyear::prelude::client::Authentication;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use shared::auth::{
+    AuthErrorBody, AuthErrorCode, AuthPayload, Key, MIN_CLIENT_SECRET_LEN, NewClientPayload,
+    ServerInfo, TokenResponse, UserData,
+};
+use shared::settings::{SERVER_ADDR, SHARED_SETTINGS};
+use tower::ServiceExt;
+
+use super::stepper::Stepper;
+use crate::accounts::{Account, AccountStore, AccountStoreError};
+use crate::auth::{TokenSettings, auth_router};
+use crate::config::RateLimitConfig;
+use crate::rate_limit::RateLimits;
+
+const PRIVATE_KEY: Key = [7; 32];
+/// Body size limit when reading responses
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Keeps accounts in memory only, so that tests don't touch the disk.
+#[derive(Default)]
+struct MemoryAccountStore(RwLock<HashMap<u64, Account>>);
+
+impl AccountStore for MemoryAccountStore {
+    fn contains(&self, client_id: u64) -> bool {
+        self.0.read().unwrap().contains_key(&client_id)
+    }
+
+    fn get(&self, client_id: u64) -> Option<Account> {
+        self.0.read().unwrap().get(&client_id).cloned()
+    }
+
+    fn insert(&self, client_id: u64, account: Account) -> Result<bool, AccountStoreError> {
+        let mut accounts = self.0.write().unwrap();
+        if accounts.contains_key(&client_id) {
+            return Ok(false);
+        }
+        accounts.insert(client_id, account);
+        Ok(true)
+    }
+}
+
+fn test_router(rate_limits: RateLimitConfig) -> Router {
+    let token_settings = TokenSettings {
+        game_server_addr: SERVER_ADDR,
+        protocol_id: SHARED_SETTINGS.protocol_id,
+        private_key: PRIVATE_KEY,
+        expire_secs: 30,
+        timeout_secs: 15,
+    };
+    let server_info = ServerInfo {
+        certificate_digest: String::new(),
+        game_server_addr: SERVER_ADDR,
+        protocol_id: SHARED_SETTINGS.protocol_id,
+    };
+    auth_router(
+        token_settings,
+        server_info,
+        Arc::new(MemoryAccountStore::default()),
+        Arc::new(RateLimits::new(&rate_limits)),
+    )
+    // what `into_make_service_with_connect_info` provides when serving
+    .layer(MockConnectInfo(SocketAddr::new(
+        Ipv4Addr::LOCALHOST.into(),
+        4242,
+    )))
+}
+
+fn secret() -> String {
+    "s".repeat(MIN_CLIENT_SECRET_LEN)
+}
+
+/// Posts `payload` as JSON, and decodes the JSON response on success or the error body otherwise.
+async fn post<T: DeserializeOwned>(
+    router: &Router,
+    path: &str,
+    payload: &impl Serialize,
+) -> Result<T, (StatusCode, AuthErrorBody)> {
+    let request = Request::post(path)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(payload).unwrap()))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), MAX_BODY_BYTES)
+        .await
+        .unwrap();
+    if status.is_success() {
+        Ok(serde_json::from_slice(&bytes).expect("invalid response body"))
+    } else {
+        Err((
+            status,
+            serde_json::from_slice(&bytes).expect("invalid error body"),
+        ))
+    }
+}
+
+async fn create_client(router: &Router, display_name: Option<&str>) -> TokenResponse {
+    let payload = NewClientPayload {
+        client_secret: secret(),
+        display_name: display_name.map(str::to_string),
+    };
+    post(router, "/create_client", &payload)
+        .await
+        .unwrap_or_else(|(status, body)| panic!("create_client failed: {status} {body:?}"))
+}
+
+async fn connect_client(
+    router: &Router,
+    client_id: u64,
+    client_secret: String,
+) -> Result<TokenResponse, (StatusCode, AuthErrorBody)> {
+    let payload = AuthPayload {
+        client_id,
+        client_secret,
+    };
+    post(router, "/connect_client", &payload).await
+}
+
+#[tokio::test]
+async fn created_client_can_connect_again() {
+    let router = test_router(RateLimitConfig::default());
+    let created = create_client(&router, None).await;
+    assert!(!created.token.is_empty());
+
+    let connected = connect_client(&router, created.client_id, secret())
+        .await
+        .expect("connect_client with the creation secret");
+    assert_eq!(connected.client_id, created.client_id);
+    assert!(!connected.token.is_empty());
+}
+
+#[tokio::test]
+async fn wrong_secret_is_unauthorized() {
+    let router = test_router(RateLimitConfig::default());
+    let created = create_client(&router, None).await;
+
+    let (status, body) = connect_client(&router, created.client_id, "x".repeat(40))
+        .await
+        .err()
+        .expect("a wrong secret is refused");
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body.code, AuthErrorCode::WrongCredentials);
+}
+
+#[tokio::test]
+async fn unknown_client_is_not_found() {
+    let router = test_router(RateLimitConfig::default());
+
+    let (status, body) = connect_client(&router, 42, secret())
+        .await
+        .err()
+        .expect("an unknown client is refused");
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, AuthErrorCode::UnknownClient);
+}
+
+#[tokio::test]
+async fn repeated_wrong_secrets_lock_the_client_out() {
+    let router = test_router(RateLimitConfig {
+        max_failed_logins: 2,
+        ..limits_without_throttling()
+    });
+    let created = create_client(&router, None).await;
+    for _ in 0..2 {
+        let (status, _) = connect_client(&router, created.client_id, "x".repeat(40))
+            .await
+            .err()
+            .expect("a wrong secret is refused");
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // even the right secret is refused while locked out
+    let (status, body) = connect_client(&router, created.client_id, secret())
+        .await
+        .err()
+        .expect("a locked out client is refused");
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body.code, AuthErrorCode::TooManyRequests);
+    assert!(body.retry_after_secs.is_some_and(|secs| secs > 0));
+}
+
+/// Limits high enough to never throttle a test.
+fn limits_without_throttling() -> RateLimitConfig {
+    RateLimitConfig {
+        ip_burst: 1000,
+        client_id_burst: 1000,
+        ..RateLimitConfig::default()
+    }
+}
+
+#[test]
+fn token_is_accepted_by_the_game_server() {
+    let router = test_router(RateLimitConfig::default());
+    // the handlers hash secrets on the blocking threads of tokio
+    let created = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(create_client(&router, Some("Alice")));
+
+    let connect_token =
+        ConnectToken::try_from_bytes(&created.token).expect("the token can be decoded");
+    let mut stepper = Stepper::with_authentications(
+        PRIVATE_KEY,
+        vec![(created.client_id, Authentication::Token(connect_token))],
+    );
+    // the server only accepts tokens it can decrypt with its private key
+    stepper.connect();
+
+    let user_data = stepper
+        .server_app
+        .world()
+        .get::<NetcodeServer>(stepper.server_entity)
+        .and_then(|server| server.user_data(created.client_id))
+        .and_then(|bytes| UserData::from_bytes(&bytes))
+        .expect("the token carries the account details");
+    assert_eq!(user_data.account_id, created.client_id);
+    assert_eq!(user_data.display_name, "Alice");
+}
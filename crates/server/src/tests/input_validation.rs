@@ -0,0 +1,187 @@
+use avian2d::prelude::Position;
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::ActionState;
+use lightyear::connection::client::Disconnecting;
+use lightyear::prelude::input::InputBuffer;
+use lightyear::prelude::*;
+use shared::protocol::{Dead, PlayerActions};
+
+use super::stepper::Stepper;
+use crate::config::{InputValidationConfig, ServerConfig};
+use crate::game::RespawnTimer;
+use crate::input_validation::Flagged;
+
+/// Half a second, so that the tests cover many windows.
+const WINDOW_SECS: f32 = 0.5;
+/// Frames for a few windows to be checked.
+const VALIDATION_FRAMES: usize = 200;
+
+/// A stepper whose client joined and is sending inputs.
+fn sending_inputs(configure: impl FnOnce(&mut InputValidationConfig)) -> Stepper {
+    let mut stepper = Stepper::new();
+    {
+        let mut config = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>();
+        config.input_validation.window_secs = WINDOW_SECS;
+        configure(&mut config.input_validation);
+    }
+    stepper.connect();
+    stepper.join_game(0);
+    stepper.press(0, &[PlayerActions::Up]);
+    let sending = stepper.frame_step_until(VALIDATION_FRAMES, |stepper| {
+        stepper.server_player(0).is_some_and(|player| {
+            stepper
+                .server_app
+                .world()
+                .get::<InputBuffer<ActionState<PlayerActions>>>(player)
+                .is_some()
+        })
+    });
+    assert!(sending, "the server did not receive inputs from the client");
+    stepper
+}
+
+fn flagged(stepper: &Stepper) -> Option<&Flagged> {
+    stepper
+        .server_app
+        .world()
+        .get::<Flagged>(stepper.clients[0].client_of_entity)
+}
+
+/// Writes an input in the server's buffer for client 0, `lead` ticks ahead of the server,
+/// as if a modified client sent it.
+fn forge_input(stepper: &mut Stepper, lead: i16) {
+    let player = stepper.server_player(0).expect("client 0 joined");
+    let world = stepper.server_app.world_mut();
+    let tick = world.resource::<LocalTimeline>().tick();
+    world
+        .get_mut::<InputBuffer<ActionState<PlayerActions>>>(player)
+        .expect("the client sends inputs")
+        .set(tick + lead, ActionState::default());
+}
+
+#[test]
+fn honest_client_is_not_flagged() {
+    let mut stepper = sending_inputs(|_| {});
+    stepper.press(0, &[PlayerActions::Up, PlayerActions::Right]);
+    stepper.frame_steps(VALIDATION_FRAMES);
+    stepper.press(0, &[PlayerActions::Down, PlayerActions::Left]);
+    stepper.frame_steps(VALIDATION_FRAMES);
+
+    let player = stepper.server_player(0).expect("client 0 joined");
+    stepper
+        .server_app
+        .world_mut()
+        .entity_mut(player)
+        .insert((Dead, RespawnTimer::default()));
+    let respawned = stepper.frame_step_until(VALIDATION_FRAMES * 2, |stepper| {
+        stepper.server_app.world().get::<Dead>(player).is_none()
+    });
+    assert!(respawned, "the player did not respawn");
+    stepper.press(0, &[PlayerActions::Up, PlayerActions::Left]);
+    stepper.frame_steps(VALIDATION_FRAMES);
+
+    assert!(
+        flagged(&stepper).is_none(),
+        "honest client flagged: {:?}",
+        flagged(&stepper)
+    );
+}
+
+#[test]
+fn inputs_ahead_of_the_server_are_flagged() {
+    let mut stepper = sending_inputs(|_| {});
+    let flagged_ahead = stepper.frame_step_until(VALIDATION_FRAMES, |stepper| {
+        // more than the default max_input_lead_ms of 1s
+        forge_input(stepper, 200);
+        flagged(stepper).is_some()
+    });
+    assert!(flagged_ahead, "inputs far ahead were not flagged");
+    assert!(
+        flagged(&stepper)
+            .is_some_and(|flagged| flagged.last_reason.contains("ahead of the server")),
+        "{:?}",
+        flagged(&stepper)
+    );
+}
+
+#[test]
+fn inputs_sent_too_fast_are_flagged() {
+    let mut stepper = sending_inputs(|config| {
+        // only check the input rate
+        config.max_input_lead_ms = 60_000;
+    });
+    // two ticks of inputs per server tick, like a client running its clock twice as fast
+    let mut lead = 0;
+    let flagged_fast = stepper.frame_step_until(VALIDATION_FRAMES, |stepper| {
+        lead += 1;
+        forge_input(stepper, lead);
+        flagged(stepper).is_some()
+    });
+    assert!(flagged_fast, "inputs sent too fast were not flagged");
+    assert!(
+        flagged(&stepper).is_some_and(|flagged| flagged.last_reason.contains("sent inputs for")),
+        "{:?}",
+        flagged(&stepper)
+    );
+}
+
+#[test]
+fn teleported_players_are_flagged() {
+    let mut stepper = sending_inputs(|_| {});
+    let player = stepper.server_player(0).expect("client 0 joined");
+    let flagged_moved = stepper.frame_step_until(VALIDATION_FRAMES, |stepper| {
+        // much further than the max speed allows in a window
+        stepper
+            .server_app
+            .world_mut()
+            .get_mut::<Position>(player)
+            .expect("players have a position")
+            .0 += Vec2::new(1000.0, 0.0);
+        flagged(stepper).is_some()
+    });
+    assert!(flagged_moved, "teleported player was not flagged");
+    assert!(
+        flagged(&stepper).is_some_and(|flagged| flagged.last_reason.starts_with("moved")),
+        "{:?}",
+        flagged(&stepper)
+    );
+}
+
+/// Connection entities that got `Disconnecting`
+#[derive(Resource, Default)]
+struct DisconnectingLinks(Vec<Entity>);
+
+#[test]
+fn clients_are_disconnected_after_too_many_violations() {
+    let mut stepper = sending_inputs(|config| config.disconnect_after = 2);
+    stepper
+        .server_app
+        .init_resource::<DisconnectingLinks>()
+        .add_observer(
+            |trigger: On<Add, Disconnecting>, mut links: ResMut<DisconnectingLinks>| {
+                links.0.push(trigger.entity);
+            },
+        );
+    let client_of_entity = stepper.clients[0].client_of_entity;
+
+    let disconnecting = stepper.frame_step_until(VALIDATION_FRAMES, |stepper| {
+        let links = stepper.server_app.world().resource::<DisconnectingLinks>();
+        if !links.0.is_empty() {
+            return true;
+        }
+        forge_input(stepper, 200);
+        false
+    });
+    assert!(disconnecting, "the client was not disconnected");
+    assert_eq!(
+        stepper
+            .server_app
+            .world()
+            .resource::<DisconnectingLinks>()
+            .0,
+        vec![client_of_entity]
+    );
+}
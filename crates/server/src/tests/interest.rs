@@ -0,0 +1,102 @@
+use avian2d::prelude::Position;
+use bevy::prelude::*;
+use lightyear::prelude::{PeerId, Replicated};
+use shared::protocol::Projectile;
+
+use super::stepper::Stepper;
+use crate::interest::InterestRadius;
+
+/// Frames for a visibility change to reach the clients, including the exit hold.
+const REPLICATION_FRAMES: usize = 200;
+
+#[test]
+fn players_are_replicated_by_distance() {
+    let mut stepper = Stepper::with_clients(2);
+    stepper.connect();
+    stepper.join_game(0);
+    stepper.join_game(1);
+
+    // without a map, both players spawn at the origin
+    assert!(
+        stepper.frame_step_until(REPLICATION_FRAMES, |stepper| {
+            stepper.client_sees_player(0, 1) && stepper.client_sees_player(1, 0)
+        }),
+        "nearby players should see each other"
+    );
+
+    let far_away = Vec2::new(InterestRadius::PLAYER.exit * 2.0, 0.0);
+    let player = stepper.server_player(1).expect("client 1 joined");
+    stepper
+        .server_app
+        .world_mut()
+        .get_mut::<Position>(player)
+        .expect("players have a position")
+        .0 = far_away;
+
+    assert!(
+        stepper.frame_step_until(REPLICATION_FRAMES, |stepper| {
+            !stepper.client_sees_player(0, 1) && !stepper.client_sees_player(1, 0)
+        }),
+        "players out of range should not see each other"
+    );
+    // a client always sees its own player
+    assert!(stepper.client_sees_player(1, 1));
+}
+
+#[test]
+fn projectiles_are_only_replicated_in_range() {
+    let mut stepper = Stepper::with_clients(2);
+    stepper.connect();
+    stepper.join_game(0);
+    stepper.join_game(1);
+    stepper.frame_steps(10);
+
+    // the shooter is out of range of client 0, which is at the origin
+    let far_away = Vec2::new(InterestRadius::PLAYER.exit * 2.0, 0.0);
+    let shooter = stepper.server_player(1).expect("client 1 joined");
+    stepper
+        .server_app
+        .world_mut()
+        .get_mut::<Position>(shooter)
+        .expect("players have a position")
+        .0 = far_away;
+    let owner = PeerId::Netcode(stepper.clients[1].id);
+    let projectile = stepper
+        .server_app
+        .world_mut()
+        .spawn((Projectile { owner }, Position(far_away)))
+        .id();
+
+    assert!(
+        stepper.frame_step_until(REPLICATION_FRAMES, |stepper| {
+            client_sees_projectile(stepper, 1)
+        }),
+        "the shooter should see its projectile"
+    );
+    assert!(
+        !client_sees_projectile(&mut stepper, 0),
+        "projectiles out of range should not be replicated"
+    );
+
+    stepper
+        .server_app
+        .world_mut()
+        .get_mut::<Position>(projectile)
+        .expect("projectiles have a position")
+        .0 = Vec2::ZERO;
+    assert!(
+        stepper.frame_step_until(REPLICATION_FRAMES, |stepper| {
+            client_sees_projectile(stepper, 0)
+        }),
+        "projectiles in range should be replicated"
+    );
+}
+
+fn client_sees_projectile(stepper: &mut Stepper, client: usize) -> bool {
+    let world = stepper.clients[client].app.world_mut();
+    world
+        .query_filtered::<&Projectile, With<Replicated>>()
+        .iter(world)
+        .next()
+        .is_some()
+}
@@ -0,0 +1,26 @@
+use bevy::prelude::*;
+use shared::protocol::PlayerId;
+
+use super::stepper::Stepper;
+
+#[test]
+fn join_game_twice_spawns_a_single_player() {
+    let mut stepper = Stepper::new();
+    stepper.connect();
+
+    // both in the same frame, and once more later
+    stepper.join_game(0);
+    stepper.join_game(0);
+    stepper.frame_steps(10);
+    stepper.join_game(0);
+    stepper.frame_steps(10);
+
+    let world = stepper.server_app.world_mut();
+    let players: Vec<_> = world
+        .query::<(Entity, &PlayerId)>()
+        .iter(world)
+        .map(|(entity, _)| entity)
+        .collect();
+    assert_eq!(players.len(), 1);
+    assert_eq!(stepper.server_player(0), Some(players[0]));
+}
@@ -0,0 +1,11 @@
+use shared::game::map::content_hash;
+
+#[test]
+fn map_hash_ignores_line_endings() {
+    let lf = b"#####\n#S  #\n#####\n";
+    let crlf = b"#####\r\n#S  #\r\n#####\r\n";
+    assert_eq!(content_hash(lf), content_hash(crlf));
+    // other changes are still detected
+    assert_ne!(content_hash(lf), content_hash(b"#####\n# S #\n#####\n"));
+    assert_ne!(content_hash(b"#\r#"), content_hash(b"##"));
+}
@@ -0,0 +1,10 @@
+mod accounts;
+mod auth;
+mod input_validation;
+mod interest;
+mod join_game;
+mod map;
+mod movement;
+mod projectiles;
+mod rate_limit;
+pub(crate) mod stepper;
@@ -0,0 +1,85 @@
+use avian2d::prelude::LinearVelocity;
+use bevy::prelude::*;
+use lightyear::prelude::{Controlled, Predicted, Replicated};
+use shared::game::movement::{MovementSettings, PlayerClass};
+use shared::protocol::PlayerActions;
+
+use super::stepper::Stepper;
+use crate::config::ServerConfig;
+
+/// Frames for the player to reach its max speed, on the client and on the server.
+const MOVEMENT_FRAMES: usize = 200;
+
+#[test]
+fn clients_move_with_the_server_settings() {
+    let mut stepper = Stepper::new();
+    let mut settings = MovementSettings::default();
+    settings.scout.max_speed = 300.0;
+    settings.scout.normalize_diagonal = false;
+    {
+        let mut config = stepper
+            .server_app
+            .world_mut()
+            .resource_mut::<ServerConfig>();
+        config.movement = settings.clone();
+        config.player_class = PlayerClass::Scout;
+    }
+    stepper.connect();
+
+    let received = stepper.frame_step_until(10, |stepper| {
+        *stepper.clients[0]
+            .app
+            .world()
+            .resource::<MovementSettings>()
+            == settings
+    });
+    assert!(received, "the client did not receive the movement settings");
+
+    stepper.join_game(0);
+    let replicated = stepper.frame_step_until(20, |stepper| {
+        let world = stepper.clients[0].app.world_mut();
+        world
+            .query_filtered::<&PlayerClass, With<Replicated>>()
+            .iter(world)
+            .any(|class| *class == PlayerClass::Scout)
+    });
+    assert!(replicated, "the player class was not replicated");
+
+    // diagonals are not normalized: the player reaches max_speed with the same speed on both axes
+    let expected = Vec2::splat(settings.scout.max_speed / 2f32.sqrt());
+    stepper.press(0, &[PlayerActions::Up, PlayerActions::Right]);
+    let at_max_speed = stepper.frame_step_until(MOVEMENT_FRAMES, |stepper| {
+        let predicted = predicted_velocity(stepper);
+        let server = server_velocity(stepper);
+        predicted.is_some_and(|velocity| velocity.abs_diff_eq(expected, 0.1))
+            && server.is_some_and(|velocity| velocity.abs_diff_eq(expected, 0.1))
+    });
+    assert!(
+        at_max_speed,
+        "predicted velocity {:?} and server velocity {:?} should be {expected}",
+        predicted_velocity(&mut stepper),
+        server_velocity(&stepper)
+    );
+    // faster than the default scout, which normalizes diagonals and is capped at 200
+    assert!(expected.length() > MovementSettings::default().scout.max_speed);
+}
+
+/// Velocity of the player predicted by client 0.
+fn predicted_velocity(stepper: &mut Stepper) -> Option<Vec2> {
+    let world = stepper.clients[0].app.world_mut();
+    world
+        .query_filtered::<&LinearVelocity, (With<Predicted>, With<Controlled>)>()
+        .iter(world)
+        .next()
+        .map(|velocity| velocity.0)
+}
+
+/// Velocity of the player of client 0 on the server.
+fn server_velocity(stepper: &Stepper) -> Option<Vec2> {
+    let player = stepper.server_player(0)?;
+    stepper
+        .server_app
+        .world()
+        .get::<LinearVelocity>(player)
+        .map(|velocity| velocity.0)
+}
@@ -0,0 +1,56 @@
+use avian2d::prelude::CollisionStart;
+use bevy::prelude::*;
+use lightyear::prelude::PeerId;
+use shared::game::projectile::{PROJECTILE_LIFETIME_TICKS, ProjectileLifetime};
+use shared::protocol::{Health, Projectile};
+
+use super::stepper::Stepper;
+
+/// Frames for the players to be spawned on the server.
+const JOIN_FRAMES: usize = 100;
+
+#[test]
+fn a_projectile_only_hits_one_player() {
+    let mut stepper = Stepper::with_clients(3);
+    stepper.connect();
+    for client in 0..3 {
+        stepper.join_game(client);
+    }
+    assert!(
+        stepper.frame_step_until(JOIN_FRAMES, |stepper| {
+            (0..3).all(|client| stepper.server_player(client).is_some())
+        }),
+        "all clients should have joined"
+    );
+    let targets = [1, 2].map(|client| stepper.server_player(client).unwrap());
+    let owner = PeerId::Netcode(stepper.clients[0].id);
+
+    let world = stepper.server_app.world_mut();
+    let projectile = world
+        .spawn((
+            Projectile { owner },
+            ProjectileLifetime(PROJECTILE_LIFETIME_TICKS),
+        ))
+        .id();
+    // the projectile touches both players in the same frame
+    let mut commands = world.commands();
+    for target in targets {
+        commands.trigger(CollisionStart {
+            collider1: projectile,
+            collider2: target,
+            body1: Some(projectile),
+            body2: Some(target),
+        });
+    }
+    world.flush();
+
+    let damaged = targets
+        .iter()
+        .filter(|&&target| {
+            world
+                .get::<Health>(target)
+                .is_some_and(|health| health.current < health.max)
+        })
+        .count();
+    assert_eq!(damaged, 1);
+}
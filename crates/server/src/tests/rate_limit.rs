@@ -0,0 +1,147 @@
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use core::time::Duration;
+use std::time::Instant;
+
+use crate::rate_limit::{LoginLockout, RateLimiter, ip_key};
+
+#[test]
+fn burst_is_allowed_at_once() {
+    let limiter = RateLimiter::new(3, 60);
+    let now = Instant::now();
+    for _ in 0..3 {
+        assert_eq!(limiter.check_at(1u64, now), Ok(()));
+    }
+    assert!(limiter.check_at(1, now).is_err());
+    // other keys have their own bucket
+    assert_eq!(limiter.check_at(2, now), Ok(()));
+}
+
+#[test]
+fn tokens_refill_over_time() {
+    // one token per second
+    let limiter = RateLimiter::new(2, 60);
+    let now = Instant::now();
+    limiter.check_at(1u64, now).unwrap();
+    limiter.check_at(1, now).unwrap();
+    assert!(limiter.check_at(1, now).is_err());
+
+    let later = now + Duration::from_secs(1);
+    assert_eq!(limiter.check_at(1, later), Ok(()));
+    assert!(limiter.check_at(1, later).is_err());
+
+    // never more than the burst, however long the wait
+    let much_later = later + Duration::from_secs(3600);
+    assert_eq!(limiter.check_at(1, much_later), Ok(()));
+    assert_eq!(limiter.check_at(1, much_later), Ok(()));
+    assert!(limiter.check_at(1, much_later).is_err());
+}
+
+#[test]
+fn retry_after_is_the_time_until_the_next_token() {
+    // one token every 2 seconds
+    let limiter = RateLimiter::new(1, 30);
+    let now = Instant::now();
+    limiter.check_at(1u64, now).unwrap();
+    assert_eq!(limiter.check_at(1, now), Err(Duration::from_secs(2)));
+    // refused requests don't take a token
+    let retry_after = limiter
+        .check_at(1, now + Duration::from_millis(500))
+        .unwrap_err();
+    assert!(
+        retry_after.abs_diff(Duration::from_millis(1500)) < Duration::from_millis(1),
+        "{retry_after:?}"
+    );
+    assert_eq!(limiter.check_at(1, now + Duration::from_secs(2)), Ok(()));
+}
+
+#[test]
+fn new_keys_are_refused_when_all_tracked_keys_are_busy() {
+    // one token per second
+    let limiter = RateLimiter::new(1, 60).with_max_keys(2);
+    let now = Instant::now();
+    limiter.check_at(1u64, now).unwrap();
+    limiter.check_at(2, now).unwrap();
+    assert!(limiter.check_at(3, now).is_err());
+    // tracked keys are still limited as usual
+    assert!(limiter.check_at(1, now).is_err());
+    assert_eq!(limiter.check_at(2, now + Duration::from_secs(1)), Ok(()));
+}
+
+#[test]
+fn refilled_buckets_are_evicted_for_new_keys() {
+    // one token per second
+    let limiter = RateLimiter::new(1, 60).with_max_keys(2);
+    let now = Instant::now();
+    limiter.check_at(1u64, now).unwrap();
+    limiter
+        .check_at(2, now + Duration::from_millis(500))
+        .unwrap();
+
+    // bucket 1 is full again, bucket 2 isn't
+    let later = now + Duration::from_secs(1);
+    assert_eq!(limiter.check_at(3, later), Ok(()));
+    assert!(limiter.check_at(3, later).is_err());
+    // bucket 2 was kept with its limit, bucket 1 was evicted
+    assert!(limiter.check_at(2, later).is_err());
+    assert!(limiter.check_at(4, later).is_err());
+}
+
+#[test]
+fn lockout_after_too_many_failures() {
+    let lockout = LoginLockout::new(3, Duration::from_secs(60));
+    let now = Instant::now();
+    lockout.record_failure_at(1, now);
+    lockout.record_failure_at(1, now);
+    assert_eq!(lockout.check_at(1, now), Ok(()));
+    lockout.record_failure_at(1, now);
+    assert_eq!(lockout.check_at(1, now), Err(Duration::from_secs(60)));
+    assert_eq!(
+        lockout.check_at(1, now + Duration::from_secs(20)),
+        Err(Duration::from_secs(40))
+    );
+    // other client ids are not locked out
+    assert_eq!(lockout.check_at(2, now), Ok(()));
+}
+
+#[test]
+fn lockout_expires() {
+    let lockout = LoginLockout::new(1, Duration::from_secs(60));
+    let now = Instant::now();
+    lockout.record_failure_at(1, now);
+    assert!(lockout.check_at(1, now).is_err());
+    let expired = now + Duration::from_secs(60);
+    assert_eq!(lockout.check_at(1, expired), Ok(()));
+    // failures were forgotten with the lockout
+    assert_eq!(
+        lockout.check_at(1, expired + Duration::from_secs(1)),
+        Ok(())
+    );
+}
+
+#[test]
+fn success_resets_failures() {
+    let lockout = LoginLockout::new(2, Duration::from_secs(60));
+    let now = Instant::now();
+    lockout.record_failure_at(1, now);
+    lockout.record_success(1);
+    lockout.record_failure_at(1, now);
+    assert_eq!(lockout.check_at(1, now), Ok(()));
+}
+
+#[test]
+fn ipv6_clients_are_limited_by_prefix() {
+    let first: Ipv6Addr = "2001:db8:1:2:aaaa::1".parse().unwrap();
+    let same_prefix: Ipv6Addr = "2001:db8:1:2:bbbb::2".parse().unwrap();
+    let other_prefix: Ipv6Addr = "2001:db8:1:3::1".parse().unwrap();
+    assert_eq!(ip_key(first.into()), ip_key(same_prefix.into()));
+    assert_ne!(ip_key(first.into()), ip_key(other_prefix.into()));
+
+    let v4 = Ipv4Addr::new(192, 0, 2, 1);
+    assert_eq!(ip_key(v4.into()), IpAddr::V4(v4));
+    assert_ne!(
+        ip_key(v4.into()),
+        ip_key(Ipv4Addr::new(192, 0, 2, 2).into())
+    );
+    // IPv4 clients of a dual stack socket are still limited per address
+    assert_eq!(ip_key(v4.to_ipv6_mapped().into()), IpAddr::V4(v4));
+}
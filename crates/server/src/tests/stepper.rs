@@ -0,0 +1,301 @@
+//! Runs the server and headless clients in the same process, connected through crossbeam channels
+//! instead of sockets, with a manually advanced clock.
+use core::net::{Ipv4Addr, SocketAddr};
+use core::time::Duration;
+
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use bevy::time::TimeUpdateStrategy;
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::{ActionState, InputMap};
+use lightyear::crossbeam::CrossbeamIo;
+use lightyear::netcode::client_plugin::NetcodeConfig as ClientNetcodeConfig;
+use lightyear::netcode::{NetcodeClient, NetcodeServer};
+use lightyear::prelude::client::{Authentication, ClientPlugins};
+use lightyear::prelude::server::{NetcodeConfig, ServerPlugins, Start};
+use lightyear::prelude::*;
+use shared::SharedPlugin;
+use shared::auth::Key;
+use shared::protocol::{ChannelPreGame, JoinGame, PlayerActions, PlayerId};
+use shared::settings::{ASSETS_PATH, FIXED_TIMESTEP_HZ, SERVER_ADDR, SHARED_SETTINGS};
+
+use crate::game::{GameServerPlugin, PlayerEntity};
+use crate::new_headless_app;
+
+/// Frames to wait for the clients to connect before giving up
+const MAX_CONNECTION_FRAMES: usize = 100;
+/// Port of the first client, the others use the next ones
+const FIRST_CLIENT_PORT: u16 = 1234;
+
+/// A headless client: lightyear and the shared plugins, without rendering nor the client crate UI.
+pub(crate) struct TestClient {
+    pub app: App,
+    /// The `Client` entity in `app`
+    pub entity: Entity,
+    /// The client's connection entity on the server
+    pub client_of_entity: Entity,
+    pub id: u64,
+}
+
+pub(crate) struct Stepper {
+    pub server_app: App,
+    pub server_entity: Entity,
+    pub clients: Vec<TestClient>,
+    tick_duration: Duration,
+    current_time: bevy::platform::time::Instant,
+}
+
+impl Stepper {
+    /// Creates the apps with a single client, connected but not started.
+    pub fn new() -> Self {
+        Self::with_clients(1)
+    }
+
+    /// Creates the apps with `count` clients, connected but not started.
+    /// Client ids start at 1.
+    pub fn with_clients(count: usize) -> Self {
+        let clients = (1..=count as u64)
+            .map(|id| {
+                let authentication = Authentication::Manual {
+                    server_addr: SERVER_ADDR,
+                    client_id: id,
+                    private_key: Key::default(),
+                    protocol_id: SHARED_SETTINGS.protocol_id,
+                };
+                (id, authentication)
+            })
+            .collect();
+        Self::with_authentications(Key::default(), clients)
+    }
+
+    /// Creates the apps with a client per `(client_id, authentication)`, connected but not started.
+    /// The server signs connect tokens with `private_key`.
+    pub fn with_authentications(private_key: Key, clients: Vec<(u64, Authentication)>) -> Self {
+        let tick_duration = Duration::from_secs_f64(1.0 / FIXED_TIMESTEP_HZ);
+
+        let mut server_app = new_headless_app();
+        server_app.add_plugins((ServerPlugins { tick_duration }, SharedPlugin));
+        server_app.add_plugins(GameServerPlugin);
+        let server_entity = server_app
+            .world_mut()
+            .spawn((
+                NetcodeServer::new(NetcodeConfig {
+                    protocol_id: SHARED_SETTINGS.protocol_id,
+                    private_key,
+                    ..default()
+                }),
+                LocalAddr(SERVER_ADDR),
+            ))
+            .id();
+
+        let clients = clients
+            .into_iter()
+            .enumerate()
+            .map(|(index, (id, authentication))| {
+                let client_addr =
+                    SocketAddr::new(Ipv4Addr::LOCALHOST.into(), FIRST_CLIENT_PORT + index as u16);
+                let (client_io, server_io) = CrossbeamIo::new_pair();
+                let client_of_entity = server_app
+                    .world_mut()
+                    .spawn((
+                        LinkOf {
+                            server: server_entity,
+                        },
+                        Link::new(None),
+                        PeerAddr(client_addr),
+                        Linked,
+                        server_io,
+                    ))
+                    .id();
+
+                let mut app = test_client_app();
+                app.add_plugins((ClientPlugins { tick_duration }, SharedPlugin));
+                let entity = app
+                    .world_mut()
+                    .spawn((
+                        Client::default(),
+                        Link::new(None),
+                        LocalAddr(client_addr),
+                        PeerAddr(SERVER_ADDR),
+                        Linked,
+                        ReplicationReceiver::default(),
+                        PredictionManager::default(),
+                        NetcodeClient::new(authentication, ClientNetcodeConfig::default())
+                            .expect("failed to create the netcode client"),
+                        client_io,
+                    ))
+                    .id();
+                TestClient {
+                    app,
+                    entity,
+                    client_of_entity,
+                    id,
+                }
+            })
+            .collect();
+
+        Self {
+            server_app,
+            server_entity,
+            clients,
+            tick_duration,
+            current_time: bevy::platform::time::Instant::now(),
+        }
+    }
+
+    /// Starts the server and connects the clients, panics if one of them doesn't connect.
+    pub fn connect(&mut self) {
+        self.server_app.world_mut().trigger(Start {
+            entity: self.server_entity,
+        });
+        for client in &mut self.clients {
+            client.app.world_mut().trigger(Connect {
+                entity: client.entity,
+            });
+        }
+        let connected = self.frame_step_until(MAX_CONNECTION_FRAMES, |stepper| {
+            stepper
+                .clients
+                .iter()
+                .all(|client| client.app.world().get::<Connected>(client.entity).is_some())
+        });
+        assert!(
+            connected,
+            "clients did not connect after {MAX_CONNECTION_FRAMES} frames"
+        );
+    }
+
+    /// Advances the clock by one tick, and updates the clients then the server.
+    pub fn frame_step(&mut self) {
+        self.current_time += self.tick_duration;
+        let apps = self
+            .clients
+            .iter_mut()
+            .map(|client| &mut client.app)
+            .chain([&mut self.server_app]);
+        for app in apps {
+            app.insert_resource(TimeUpdateStrategy::ManualInstant(self.current_time));
+            app.update();
+        }
+    }
+
+    pub fn frame_steps(&mut self, count: usize) {
+        for _ in 0..count {
+            self.frame_step();
+        }
+    }
+
+    /// Steps until `condition` holds, for at most `max_frames`. Returns whether it held.
+    pub fn frame_step_until(
+        &mut self,
+        max_frames: usize,
+        mut condition: impl FnMut(&mut Self) -> bool,
+    ) -> bool {
+        for _ in 0..max_frames {
+            if condition(self) {
+                return true;
+            }
+            self.frame_step();
+        }
+        condition(self)
+    }
+
+    /// Sends a message from a client to the server, it is received on the next frames.
+    pub fn send_to_server<C: Channel, M: Message>(&mut self, client: usize, message: M) {
+        let client = &mut self.clients[client];
+        client
+            .app
+            .world_mut()
+            .get_mut::<MessageSender<M>>(client.entity)
+            .expect("the client can send this message")
+            .send::<C>(message);
+    }
+
+    pub fn join_game(&mut self, client: usize) {
+        self.send_to_server::<ChannelPreGame, _>(client, JoinGame);
+    }
+
+    /// The player of a client, on the server.
+    pub fn server_player(&self, client: usize) -> Option<Entity> {
+        self.server_app
+            .world()
+            .get::<PlayerEntity>(self.clients[client].client_of_entity)
+            .map(|player| player.0)
+    }
+
+    /// Press `actions` on the player of `client` until the next call, like a bot does.
+    pub fn press(&mut self, client: usize, actions: &[PlayerActions]) {
+        self.clients[client]
+            .app
+            .insert_resource(PressedActions(actions.to_vec()));
+    }
+
+    /// Whether the player of `other` is replicated to `client`.
+    pub fn client_sees_player(&mut self, client: usize, other: usize) -> bool {
+        let other_id = PeerId::Netcode(self.clients[other].id);
+        let world = self.clients[client].app.world_mut();
+        world
+            .query_filtered::<&PlayerId, With<Replicated>>()
+            .iter(world)
+            .any(|player_id| player_id.0 == other_id)
+    }
+}
+
+/// Actions a test client presses, see [`Stepper::press`].
+#[derive(Resource, Default)]
+struct PressedActions(Vec<PlayerActions>);
+
+/// The controlled player gets an empty `InputMap`, so that its inputs are sent to the server.
+fn add_input_map(
+    trigger: On<Add, (PlayerId, Predicted)>,
+    controlled: Query<(), (With<Predicted>, With<PlayerId>, With<Controlled>)>,
+    mut commands: Commands,
+) {
+    if controlled.contains(trigger.entity) {
+        commands
+            .entity(trigger.entity)
+            .insert(InputMap::<PlayerActions>::default());
+    }
+}
+
+/// Runs after leafwing updated the action states from the (empty) input maps.
+fn press_actions(
+    pressed: Res<PressedActions>,
+    mut action_states: Query<&mut ActionState<PlayerActions>, With<InputMap<PlayerActions>>>,
+) {
+    for mut action_state in action_states.iter_mut() {
+        for action in [
+            PlayerActions::Up,
+            PlayerActions::Down,
+            PlayerActions::Left,
+            PlayerActions::Right,
+            PlayerActions::Fire,
+        ] {
+            if pressed.0.contains(&action) {
+                action_state.press(&action);
+            } else {
+                action_state.release(&action);
+            }
+        }
+    }
+}
+
+/// Clients don't need the server's logging and diagnostics plugins.
+fn test_client_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin {
+            file_path: ASSETS_PATH.to_string(),
+            ..default()
+        },
+        StatesPlugin,
+    ));
+    app.init_resource::<PressedActions>();
+    app.add_observer(add_input_map);
+    app.add_systems(
+        PreUpdate,
+        press_actions.in_set(InputManagerSystem::ManualControl),
+    );
+    app
+}
@@ -0,0 +1,105 @@
+//! Compares the cost of finding the entities in the interest radius of every player,
+//! with the server's default world of 100x100 circles:
+//! - `grid`: querying the [`SpatialGrid`]
+//! - `scan`: checking the distance to every circle, as interest management used to do
+//!
+//! Run with `cargo bench -p shared`.
+use bevy::prelude::*;
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use shared::spatial_grid::SpatialGrid;
+use std::hint::black_box;
+
+const INTEREST_RADIUS: f32 = 150.0;
+const OBSTACLE_GAP: f32 = 50.0;
+const OBSTACLES_ROW_COL: i32 = 50;
+const PLAYER_COUNTS: [usize; 4] = [10, 100, 500, 1000];
+
+fn circles() -> Vec<(Entity, Vec2)> {
+    (-OBSTACLES_ROW_COL..OBSTACLES_ROW_COL)
+        .flat_map(|x| (-OBSTACLES_ROW_COL..OBSTACLES_ROW_COL).map(move |y| (x, y)))
+        .enumerate()
+        .map(|(i, (x, y))| {
+            (
+                Entity::from_raw_u32(i as u32 + 1).unwrap(),
+                Vec2::new(x as f32 * OBSTACLE_GAP, y as f32 * OBSTACLE_GAP),
+            )
+        })
+        .collect()
+}
+
+fn players(count: usize) -> Vec<Vec2> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let extent = OBSTACLES_ROW_COL as f32 * OBSTACLE_GAP;
+    (0..count)
+        .map(|_| {
+            Vec2::new(
+                rng.random_range(-extent..extent),
+                rng.random_range(-extent..extent),
+            )
+        })
+        .collect()
+}
+
+fn interest_management(c: &mut Criterion) {
+    let circles = circles();
+    let mut grid = SpatialGrid::new(INTEREST_RADIUS);
+    for (entity, position) in &circles {
+        grid.insert(*entity, *position);
+    }
+
+    let mut group = c.benchmark_group("interest_management");
+    for count in PLAYER_COUNTS {
+        let players = players(count);
+        group.bench_with_input(BenchmarkId::new("grid", count), &players, |b, players| {
+            b.iter(|| {
+                for player in players {
+                    black_box(grid.within_radius(*player, INTEREST_RADIUS).count());
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("scan", count), &players, |b, players| {
+            b.iter(|| {
+                for player in players {
+                    black_box(
+                        circles
+                            .iter()
+                            .filter(|(_, position)| player.distance(*position) < INTEREST_RADIUS)
+                            .count(),
+                    );
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Moving every player, as done when their `Position` changes.
+fn update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spatial_grid_update");
+    for count in PLAYER_COUNTS {
+        let players = players(count);
+        let mut grid = SpatialGrid::new(INTEREST_RADIUS);
+        let entities: Vec<Entity> = (0..count)
+            .map(|i| Entity::from_raw_u32(i as u32 + 1).unwrap())
+            .collect();
+        for (entity, position) in entities.iter().zip(&players) {
+            grid.insert(*entity, *position);
+        }
+        let mut offset = Vec2::ZERO;
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| {
+                offset += Vec2::new(3.0, -2.0);
+                for (entity, position) in entities.iter().zip(&players) {
+                    grid.insert(*entity, *position + offset);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interest_management, update);
+criterion_main!(benches);
@@ -11,9 +11,109 @@ pub const AUTH_BACKEND_PORT: u16 = 4100;
 pub const AUTH_BACKEND_ADDRESS: SocketAddr =
     SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, AUTH_BACKEND_PORT));
 
+/// Default url clients use to reach the authentication backend.
+pub const AUTH_BACKEND_URL: &str = "http://127.0.0.1:4100";
+
+/// Minimum length of the secret a client registers with.
+/// Secrets generated by the client are hex encoded random bytes, so this is 128 bits of entropy.
+pub const MIN_CLIENT_SECRET_LEN: usize = 32;
+
+/// Generate a high-entropy secret of [`MIN_CLIENT_SECRET_LEN`] characters, hex encoded so it can
+/// be stored in a prefs or json file.
+#[cfg(feature = "client")]
+pub fn generate_client_secret() -> String {
+    let mut bytes = [0u8; MIN_CLIENT_SECRET_LEN / 2];
+    getrandom::fill(&mut bytes).expect("Failed to generate a random client secret");
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
 /// A 32-byte array, used as a key for encrypting and decrypting packets and connect tokens.
 pub type Key = [u8; PRIVATE_KEY_BYTES];
 
+/// Size of the user data netcode carries in connect tokens.
+pub const USER_DATA_BYTES: usize = 256;
+
+/// Display names longer than this are truncated when embedded in [`UserData`].
+pub const MAX_DISPLAY_NAME_LEN: usize = 64;
+
+/// Version of the [`UserData`] encoding, so that the server can detect tokens it can't read.
+const USER_DATA_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Role {
+    #[default]
+    Player,
+    Moderator,
+    Admin,
+}
+
+/// Account details embedded by the auth backend in the connect token, so that the game server
+/// knows who connected without asking the auth backend.
+///
+/// Encoded as: version (1 byte), account id (8 bytes, little endian), role (1 byte),
+/// display name length (1 byte), display name (utf8), zero padding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserData {
+    pub account_id: u64,
+    pub display_name: String,
+    pub role: Role,
+}
+
+impl UserData {
+    pub fn to_bytes(&self) -> [u8; USER_DATA_BYTES] {
+        let mut bytes = [0u8; USER_DATA_BYTES];
+        bytes[0] = USER_DATA_VERSION;
+        bytes[1..9].copy_from_slice(&self.account_id.to_le_bytes());
+        bytes[9] = match self.role {
+            Role::Player => 0,
+            Role::Moderator => 1,
+            Role::Admin => 2,
+        };
+        let name = truncate_utf8(&self.display_name, MAX_DISPLAY_NAME_LEN);
+        bytes[10] = name.len() as u8;
+        bytes[11..11 + name.len()].copy_from_slice(name.as_bytes());
+        bytes
+    }
+
+    /// Returns `None` if the bytes were not written by [`UserData::to_bytes`].
+    pub fn from_bytes(bytes: &[u8; USER_DATA_BYTES]) -> Option<Self> {
+        if bytes[0] != USER_DATA_VERSION {
+            return None;
+        }
+        let account_id = u64::from_le_bytes(bytes[1..9].try_into().ok()?);
+        let role = match bytes[9] {
+            0 => Role::Player,
+            1 => Role::Moderator,
+            2 => Role::Admin,
+            _ => return None,
+        };
+        let name_len = bytes[10] as usize;
+        if name_len > MAX_DISPLAY_NAME_LEN {
+            return None;
+        }
+        let display_name = core::str::from_utf8(&bytes[11..11 + name_len])
+            .ok()?
+            .to_string();
+        Some(Self {
+            account_id,
+            display_name,
+            role,
+        })
+    }
+}
+
+/// Longest prefix of `s` of at most `max_len` bytes, cut on a char boundary.
+fn truncate_utf8(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
 /// Response when calling the authentication endpoint.
 #[derive(Reflect, Serialize, Deserialize, Clone)]
 pub struct TokenResponse {
@@ -21,6 +121,15 @@ pub struct TokenResponse {
     pub client_id: u64,
 }
 
+/// Response of the `/server_info` endpoint, describing how to reach the game server.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerInfo {
+    /// Hex encoded sha256 digest of the WebTransport certificate.
+    pub certificate_digest: String,
+    pub game_server_addr: SocketAddr,
+    pub protocol_id: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthPayload {
     pub client_id: u64,
@@ -30,4 +139,35 @@ pub struct AuthPayload {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NewClientPayload {
     pub client_secret: String,
+    /// Name shown to other players, a default one is picked if missing.
+    #[serde(default)]
+    pub display_name: Option<String>,
+}
+
+/// What went wrong in a request to the authentication backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthErrorCode {
+    WrongCredentials,
+    MissingCredentials,
+    /// No account exists for this `client_id`, a new one has to be created.
+    UnknownClient,
+    TokenCreation,
+    InvalidToken,
+    WeakSecret,
+    InvalidDisplayName,
+    AccountStorage,
+    /// Rate limited or locked out, see `retry_after_secs`.
+    TooManyRequests,
+}
+
+/// Body of the error responses of the authentication backend.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuthErrorBody {
+    pub code: AuthErrorCode,
+    /// Human readable description of the error.
+    pub error: String,
+    /// Seconds to wait before retrying, for [`AuthErrorCode::TooManyRequests`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
 }
@@ -0,0 +1,5 @@
+//! Code shared by the game client and the bots, which connect to the server the same way.
+//!
+//! Only compiled with the `client` feature, the server doesn't need it.
+pub mod auth;
+pub mod map;
@@ -0,0 +1,164 @@
+//! Requests to the authentication backend.
+use core::fmt;
+use core::time::Duration;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::auth::{AuthErrorBody, AuthErrorCode, AuthPayload, NewClientPayload, ServerInfo};
+use crate::settings::SHARED_SETTINGS;
+
+/// Why a request to the auth backend failed.
+#[derive(Debug)]
+pub enum AuthClientError {
+    /// The request could not be sent, eg: the auth backend is down.
+    Unreachable {
+        url: String,
+        error: String,
+    },
+    /// The auth backend answered with an error status.
+    Rejected {
+        url: String,
+        status: u16,
+        body: Option<AuthErrorBody>,
+    },
+    /// The response could not be decoded.
+    InvalidResponse {
+        url: String,
+        error: String,
+    },
+    UnsupportedProtocol {
+        server: u64,
+        client: u64,
+    },
+    /// `/server_info` was needed for the game server address or certificate digest, but not fetched.
+    MissingServerInfo,
+}
+
+impl AuthClientError {
+    /// The auth backend doesn't know the account anymore, a new one has to be created.
+    ///
+    /// Wrong credentials don't count: the account still exists, and replacing it would lose the
+    /// player's identity over a bad secret or a server-side mistake.
+    pub fn is_account_lost(&self) -> bool {
+        matches!(
+            self,
+            AuthClientError::Rejected {
+                body: Some(AuthErrorBody {
+                    code: AuthErrorCode::UnknownClient,
+                    ..
+                }),
+                ..
+            }
+        )
+    }
+
+    /// How long to wait before retrying, if the auth backend rate limited the request.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AuthClientError::Rejected {
+                body:
+                    Some(AuthErrorBody {
+                        code: AuthErrorCode::TooManyRequests,
+                        retry_after_secs,
+                        ..
+                    }),
+                ..
+            } => Some(Duration::from_secs(retry_after_secs.unwrap_or(1))),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AuthClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthClientError::Unreachable { url, error } => {
+                write!(f, "Could not reach {url}: {error}")
+            }
+            AuthClientError::Rejected {
+                body: Some(body), ..
+            } => match body.retry_after_secs {
+                Some(secs) => write!(f, "{}, retry in {secs}s", body.error),
+                None => write!(f, "{}", body.error),
+            },
+            AuthClientError::Rejected {
+                url,
+                status,
+                body: None,
+            } => write!(f, "Request to {url} failed with status {status}"),
+            AuthClientError::InvalidResponse { url, error } => {
+                write!(f, "Invalid response from {url}: {error}")
+            }
+            AuthClientError::UnsupportedProtocol { server, client } => write!(
+                f,
+                "Server protocol {server} is not supported by this client (protocol {client})"
+            ),
+            AuthClientError::MissingServerInfo => write!(f, "Game server address is unknown"),
+        }
+    }
+}
+
+impl std::error::Error for AuthClientError {}
+
+/// Decode the JSON response of the auth backend to a request sent to `url`.
+pub fn decode_response<T: DeserializeOwned>(
+    url: String,
+    result: ehttp::Result<ehttp::Response>,
+) -> Result<T, AuthClientError> {
+    let response = result.map_err(|error| AuthClientError::Unreachable {
+        url: url.clone(),
+        error,
+    })?;
+    if !response.ok {
+        return Err(AuthClientError::Rejected {
+            body: serde_json::from_slice(&response.bytes).ok(),
+            status: response.status,
+            url,
+        });
+    }
+    serde_json::from_slice(&response.bytes).map_err(|e| AuthClientError::InvalidResponse {
+        url,
+        error: e.to_string(),
+    })
+}
+
+/// Send a request to the auth backend and decode its JSON response.
+pub async fn fetch_json<T: DeserializeOwned>(
+    request: ehttp::Request,
+) -> Result<T, AuthClientError> {
+    let url = request.url.clone();
+    decode_response(url, ehttp::fetch_async(request).await)
+}
+
+/// Build a JSON `POST` request to the auth backend.
+fn post_json(url: String, payload: &impl Serialize) -> ehttp::Request {
+    let mut req = ehttp::Request::post(url, serde_json::to_vec(payload).unwrap());
+    req.headers
+        .insert("Content-Type", "application/json; charset=utf8");
+    req.headers.insert("Accept", "application/json");
+    req
+}
+
+/// Create an account, answered with a [`TokenResponse`](crate::auth::TokenResponse).
+pub fn create_client_request(auth_url: &str, payload: &NewClientPayload) -> ehttp::Request {
+    post_json(format!("{auth_url}/create_client"), payload)
+}
+
+/// Log in to an existing account, answered with a [`TokenResponse`](crate::auth::TokenResponse).
+pub fn connect_client_request(auth_url: &str, payload: &AuthPayload) -> ehttp::Request {
+    post_json(format!("{auth_url}/connect_client"), payload)
+}
+
+/// Get the game server address and certificate digest from the auth backend.
+pub async fn fetch_server_info(auth_url: &str) -> Result<ServerInfo, AuthClientError> {
+    let url = format!("{auth_url}/server_info");
+    let server_info: ServerInfo = fetch_json(ehttp::Request::get(url)).await?;
+    if server_info.protocol_id != SHARED_SETTINGS.protocol_id {
+        return Err(AuthClientError::UnsupportedProtocol {
+            server: server_info.protocol_id,
+            client: SHARED_SETTINGS.protocol_id,
+        });
+    }
+    Ok(server_info)
+}
@@ -0,0 +1,127 @@
+//! Load the map announced by the server, and only join the game if the local copy is the same.
+//!
+//! Bots do it too: without the walls, their predicted movement would go through them.
+use bevy::prelude::*;
+use lightyear::prelude::*;
+
+use crate::game::map::{LoadedMap, MapLoadFailed, MapSelection};
+use crate::protocol::{ChannelPreGame, JoinGame, MapInfo};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<ServerMap>();
+    app.add_observer(reset_server_map);
+    app.add_observer(reject_unloadable_map);
+    app.add_systems(Update, (receive_map_info, verify_map).chain());
+}
+
+/// State of the map announced by the server, for the current connection.
+#[derive(Resource, Debug, Default)]
+pub struct ServerMap {
+    announced: Option<MapInfo>,
+    /// The loaded map matches the announced one.
+    verified: bool,
+    /// [`JoinGame`] was sent, the server keeps our player through map changes.
+    joined: bool,
+    /// Why we disconnected because of the map, displayed instead of the disconnection reason.
+    pub rejection: Option<String>,
+}
+
+/// We disconnected because our copy of the announced map is missing or different.
+#[derive(Event, Debug)]
+pub struct MapRejected {
+    pub reason: String,
+}
+
+fn reset_server_map(_trigger: On<Add, Connected>, mut server_map: ResMut<ServerMap>) {
+    *server_map = ServerMap::default();
+}
+
+fn receive_map_info(
+    mut receiver: Query<&mut MessageReceiver<MapInfo>, With<Client>>,
+    selection: Option<Res<MapSelection>>,
+    mut server_map: ResMut<ServerMap>,
+    mut commands: Commands,
+) {
+    for mut receiver in receiver.iter_mut() {
+        for map_info in receiver.receive() {
+            info!("Server plays map {} ({:x})", map_info.path, map_info.hash);
+            if selection.as_ref().is_none_or(|s| s.0 != map_info.path) {
+                commands.insert_resource(MapSelection(map_info.path.clone()));
+            }
+            server_map.announced = Some(map_info);
+            server_map.verified = false;
+        }
+    }
+}
+
+/// Once the announced map is loaded, compare its hash with the server's.
+fn verify_map(
+    mut server_map: ResMut<ServerMap>,
+    loaded_map: Option<Res<LoadedMap>>,
+    mut client: Query<(Entity, &mut MessageSender<JoinGame>), (With<Client>, With<Connected>)>,
+    mut commands: Commands,
+) {
+    if server_map.verified || server_map.rejection.is_some() {
+        return;
+    }
+    let (Some(announced), Some(loaded_map)) = (&server_map.announced, loaded_map) else {
+        return;
+    };
+    // the previous map is still displayed, the announced one is loading
+    if loaded_map.0.path != announced.path {
+        return;
+    }
+    let Ok((client_entity, mut sender)) = client.single_mut() else {
+        return;
+    };
+    if loaded_map.0.hash != announced.hash {
+        let reason = format!(
+            "Map {} differs from the server's, update the game",
+            announced.path
+        );
+        reject_map(&mut commands, &mut server_map, client_entity, reason);
+        return;
+    }
+    server_map.verified = true;
+    if !server_map.joined {
+        sender.send::<ChannelPreGame>(JoinGame);
+        server_map.joined = true;
+    }
+}
+
+fn reject_unloadable_map(
+    trigger: On<MapLoadFailed>,
+    mut server_map: ResMut<ServerMap>,
+    client: Query<Entity, (With<Client>, With<Connected>)>,
+    mut commands: Commands,
+) {
+    let path = &trigger.event().path;
+    if server_map
+        .announced
+        .as_ref()
+        .is_none_or(|announced| &announced.path != path)
+    {
+        return;
+    }
+    let Ok(client_entity) = client.single() else {
+        return;
+    };
+    let reason = format!("Map {path} could not be loaded, update the game");
+    reject_map(&mut commands, &mut server_map, client_entity, reason);
+}
+
+fn reject_map(
+    commands: &mut Commands,
+    server_map: &mut ServerMap,
+    client_entity: Entity,
+    reason: String,
+) {
+    error!("{reason}");
+    commands.trigger(MapRejected {
+        reason: reason.clone(),
+    });
+    server_map.rejection = Some(reason);
+    commands.trigger(Disconnect {
+        entity: client_entity,
+    });
+}
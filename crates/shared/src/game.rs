@@ -1,16 +1,19 @@
 pub mod map;
+pub mod movement;
+pub mod projectile;
 
 use avian2d::{
     PhysicsPlugins,
     prelude::{
-        Collider, ColliderDensity, Gravity, Mass, MassPropertiesBundle, PhysicsInterpolationPlugin,
-        PhysicsTransformPlugin, Restitution, RigidBody,
+        Collider, ColliderDensity, ColliderDisabled, Gravity, Mass, MassPropertiesBundle,
+        PhysicsInterpolationPlugin, PhysicsTransformPlugin, Restitution, RigidBody,
     },
 };
 use bevy::prelude::*;
 use lightyear::avian2d::plugin::AvianReplicationMode;
+use lightyear::prelude::Predicted;
 
-use crate::protocol::{ColorComponent, physics::PhysicsBundle};
+use crate::protocol::{ColorComponent, Dead, PlayerId, physics::PhysicsBundle};
 
 pub fn plugin(app: &mut App) {
     app.add_plugins(lightyear::avian2d::plugin::LightyearAvianPlugin {
@@ -27,11 +30,36 @@ pub fn plugin(app: &mut App) {
     )
     .insert_resource(Gravity(Vec2::ZERO));
 
-    app.add_systems(Startup, init_walls);
+    app.add_plugins(map::plugin);
+    app.add_plugins(movement::plugin);
+    app.add_plugins(projectile::plugin);
+    app.add_observer(disable_dead_collider);
+    app.add_observer(enable_respawned_collider);
+    app.add_observer(add_predicted_player_physics);
 }
 
-pub(crate) fn init_walls(mut commands: Commands) {
-    map::create_map_3(commands.reborrow());
+/// Predicted players collide like on the server, so that prediction matches it.
+fn add_predicted_player_physics(
+    trigger: On<Add, (PlayerId, Predicted)>,
+    predicted: Query<(), (With<Predicted>, With<PlayerId>)>,
+    mut commands: Commands,
+) {
+    if predicted.contains(trigger.entity) {
+        commands
+            .entity(trigger.entity)
+            .insert(PhysicsBundle::player());
+    }
+}
+
+/// Dead players don't collide with anything
+fn disable_dead_collider(trigger: On<Add, Dead>, mut commands: Commands) {
+    commands.entity(trigger.entity).insert(ColliderDisabled);
+}
+
+fn enable_respawned_collider(trigger: On<Remove, Dead>, mut commands: Commands) {
+    commands
+        .entity(trigger.entity)
+        .try_remove::<ColliderDisabled>();
 }
 
 // Wall
@@ -43,6 +71,10 @@ pub struct WallBundle {
     color: ColorComponent,
 }
 
+/// Marker for players that can't act, eg: while their client is disconnected.
+#[derive(Component, Debug)]
+pub struct Frozen;
+
 #[derive(Component, Debug)]
 pub struct Wall {
     pub start: Vec2,
@@ -1,174 +1,197 @@
+//! Maps are asset files in `assets/maps/`, in one of two formats:
+//! - `.map.txt`: an ASCII grid, where `#` is a wall and `S` a spawn point
+//! - `.map.ron`: a list of wall segments and spawn points
+//!
+//! The map to play is chosen with the [`MapSelection`] resource. The server picks it, and announces
+//! it to clients with a [`MapInfo`] message so that they can load it and check that their copy
+//! is the same, through its [`content_hash`].
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
 use bevy::prelude::*;
+use core::fmt;
+use serde::{Deserialize, Serialize};
 
-use crate::game::WallBundle;
-
-pub fn create_map_1(mut commands: Commands) {
-    const WALL_SIZE: f32 = 3000.0;
-
-    commands.spawn(WallBundle::new(
-        Vec2::new(-WALL_SIZE, -WALL_SIZE),
-        Vec2::new(-WALL_SIZE, WALL_SIZE),
-        Color::WHITE,
-    ));
-    commands.spawn(WallBundle::new(
-        Vec2::new(-WALL_SIZE, WALL_SIZE),
-        Vec2::new(WALL_SIZE, WALL_SIZE),
-        Color::WHITE,
-    ));
-    commands.spawn(WallBundle::new(
-        Vec2::new(WALL_SIZE, WALL_SIZE),
-        Vec2::new(WALL_SIZE, -WALL_SIZE),
-        Color::WHITE,
-    ));
-    commands.spawn(WallBundle::new(
-        Vec2::new(WALL_SIZE, -WALL_SIZE),
-        Vec2::new(-WALL_SIZE, -WALL_SIZE),
-        Color::WHITE,
-    ));
-}
-
-pub fn create_map_2(mut commands: Commands) {
-    const WALL_SIZE: f32 = 3000.0;
-    const COLOR: Color = Color::WHITE;
-
-    // Helper for local use
-    let mut wall = |x1: f32, y1: f32, x2: f32, y2: f32| {
-        commands.spawn(WallBundle::new(Vec2::new(x1, y1), Vec2::new(x2, y2), COLOR));
-    };
+use crate::game::{Wall, WallBundle};
+use crate::protocol::MapInfo;
+
+/// Map played when none is configured.
+pub const DEFAULT_MAP: &str = "maps/labyrinth.map.txt";
+
+/// Size of a tile of an ASCII map, in world units.
+const ASCII_TILE_SIZE: f32 = 50.0;
+
+pub fn plugin(app: &mut App) {
+    app.init_asset::<Map>();
+    app.register_asset_loader(RonMapLoader);
+    app.register_asset_loader(AsciiMapLoader);
+    app.init_resource::<SpawnPoints>();
+    app.add_systems(
+        Update,
+        (
+            load_selected_map.run_if(resource_exists_and_changed::<MapSelection>),
+            spawn_loaded_map,
+        )
+            .chain(),
+    );
+}
+
+/// Where players can (re)spawn on the current map.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SpawnPoints(pub Vec<Vec2>);
+
+/// Asset path of the map to play, eg: `maps/labyrinth.map.txt`.
+///
+/// Changing it despawns the current map and loads the new one.
+/// Nothing is loaded until it is inserted.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct MapSelection(pub String);
+
+impl Default for MapSelection {
+    fn default() -> Self {
+        Self(DEFAULT_MAP.to_string())
+    }
+}
+
+/// The selected map, until its walls are spawned.
+#[derive(Resource, Debug)]
+struct PendingMap {
+    path: String,
+    handle: Handle<Map>,
+}
+
+/// The map whose walls are currently spawned.
+///
+/// Removed while another map is loading.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct LoadedMap(pub MapInfo);
+
+/// Triggered when the selected map can't be loaded, eg: the file is missing or invalid.
+#[derive(Event, Debug)]
+pub struct MapLoadFailed {
+    pub path: String,
+}
+
+#[derive(Asset, TypePath, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Map {
+    pub walls: Vec<WallSegment>,
+    #[serde(default)]
+    pub spawn_points: Vec<Vec2>,
+    /// [`content_hash`] of the file the map was loaded from.
+    #[serde(skip)]
+    pub hash: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WallSegment {
+    pub start: Vec2,
+    pub end: Vec2,
+}
+
+#[derive(Debug)]
+pub enum MapLoadError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+    Utf8(core::str::Utf8Error),
+}
 
-    // --- 1. Outer Boundary ---
-    wall(-WALL_SIZE, -WALL_SIZE, -WALL_SIZE, WALL_SIZE);
-    wall(-WALL_SIZE, WALL_SIZE, WALL_SIZE, WALL_SIZE);
-    wall(WALL_SIZE, WALL_SIZE, WALL_SIZE, -WALL_SIZE);
-    wall(WALL_SIZE, -WALL_SIZE, -WALL_SIZE, -WALL_SIZE);
-
-    // --- 2. The Central Cross (Broken for passage) ---
-    // Top & Bottom vertical splitters
-    wall(0.0, 600.0, 0.0, 1500.0);
-    wall(0.0, -600.0, 0.0, -1500.0);
-    // Left & Right horizontal splitters
-    wall(-600.0, 0.0, -1500.0, 0.0);
-    wall(600.0, 0.0, 1500.0, 0.0);
-
-    // --- 3. The "Inner Ring" (Four large corner boxes/rooms) ---
-    // These create 4 distinct quadrants with 200-unit wide doorways
-    let room_size = 400.0;
-    let offset = 800.0;
-    for x_sign in [-1.0, 1.0] {
-        for y_sign in [-1.0, 1.0] {
-            let cx = x_sign * offset;
-            let cy = y_sign * offset;
-            // Draw a small 3-sided "C" shape facing the center
-            wall(
-                cx - room_size,
-                cy - room_size,
-                cx + room_size,
-                cy - room_size,
-            ); // Horiz
-            wall(
-                cx - room_size,
-                cy - room_size,
-                cx - room_size,
-                cy + room_size,
-            ); // Vert
-            wall(
-                cx - room_size,
-                cy + room_size,
-                cx + room_size,
-                cy + room_size,
-            ); // Horiz
+impl fmt::Display for MapLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapLoadError::Io(e) => write!(f, "could not read map: {e}"),
+            MapLoadError::Ron(e) => write!(f, "could not parse map: {e}"),
+            MapLoadError::Utf8(e) => write!(f, "map is not valid utf8: {e}"),
         }
     }
+}
+
+impl std::error::Error for MapLoadError {}
+
+impl From<std::io::Error> for MapLoadError {
+    fn from(e: std::io::Error) -> Self {
+        MapLoadError::Io(e)
+    }
+}
+
+/// Loads `.map.ron` files, deserialized as a [`Map`].
+#[derive(Default, TypePath)]
+pub struct RonMapLoader;
+
+impl AssetLoader for RonMapLoader {
+    type Asset = Map;
+    type Settings = ();
+    type Error = MapLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Map, MapLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let mut map: Map = ron::de::from_bytes(&bytes).map_err(MapLoadError::Ron)?;
+        map.hash = content_hash(&bytes);
+        Ok(map)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.ron"]
+    }
+}
+
+/// Loads `.map.txt` files, see [`parse_ascii_map`].
+#[derive(Default, TypePath)]
+pub struct AsciiMapLoader;
+
+impl AssetLoader for AsciiMapLoader {
+    type Asset = Map;
+    type Settings = ();
+    type Error = MapLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Map, MapLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let data = core::str::from_utf8(&bytes).map_err(MapLoadError::Utf8)?;
+        let mut map = parse_ascii_map(data);
+        map.hash = content_hash(&bytes);
+        Ok(map)
+    }
 
-    // --- 4. The "Teeth" (Small cover segments scattered in the open lanes) ---
-    // North lane
-    wall(-200.0, 2200.0, 200.0, 2200.0);
-    wall(0.0, 2200.0, 0.0, 2600.0);
-
-    // South lane
-    wall(-200.0, -2200.0, 200.0, -2200.0);
-    wall(0.0, -2200.0, 0.0, -2600.0);
-
-    // --- 5. Far Corner "Bunkers" ---
-    // Top Right
-    wall(2500.0, 2500.0, 2100.0, 2500.0);
-    wall(2500.0, 2500.0, 2500.0, 2100.0);
-
-    // Bottom Left
-    wall(-2500.0, -2500.0, -2100.0, -2500.0);
-    wall(-2500.0, -2500.0, -2500.0, -2100.0);
-}
-
-const MAP_DATA: &str = "
-############################################################
-#                                                          #
-#   ##     ###        ####    ##        ####   ###         #
-#   #        #        #                 #        #         #
-#   #   ##   #            ##            #   ##   #         #
-#   #        #                 #        #        #         #
-#   #### #####             #####        ###     ##         #
-#                                                          #
-#            ####   ###            ####   ###              #
-#    ######  #        #            #        #              #
-#            #   ##   #   ######   #   ##   #   ######     #
-#   #        #        #   #    #   #        #   #    #     #
-#   #        ####  ####   #    #   ###     ##   #    #     #
-#   #    #                #    #                #    #     #
-#        ####  ####  ######    ######      ######    #     #
-#   #                                                #     #
-#        ##########  ######    ##########  ######    #     #
-#   #    #                                      #    #     #
-#        #    #########   #    #   ########     #    #     #
-#   #    #            #   #    #   #                       #
-#   ######       ##   #   ######   #   ##                  #
-#                                           #      ###     #
-#             #########            ####  ####        #     #
-#                                                    #     #
-#   #####  ###        ##########        #####  ###         #
-#            #                          #        #         #
-#   #   ##   #            ##                ##             #
-#   #        #        #        #        #        #         #
-#   ###    ###        ##########        ##########         #
-#                                                          #
-#            ###   ####            #####   ##              #
-#            #        #            #        #              #
-#   ######   #   ##   #   #  ###   #   ##   #   ######     #
-#   #    #            #   #    #   #        #   #    #     #
-#   #    #    #########        #        #####   #    #     #
-#        #                     #                #          #
-#   #         #####  ######    #    #####  ######    #     #
-#   #                                                #     #
-#   #    ##########  ######    ###   ####  ######    #     #
-#   #                     #    #                     #     #
-#   #        ##########   #        ##########        #     #
-#   #                     #                          #     #
-#   ######       ##       #            ##       ######     #
-#                #                                         #
-#            #######   ###         ##########              #
-#                                                          #
-#   ##    ####            ######        #####    #         #
-#   #        #        #        #        #        #         #
-#       ##   #        #        #        #   ##   #         #
-#            #        #        #        #        #         #
-#   #####  ###        #####    #        ##    ####         #
-#                                                          #
-############################################################";
-
-pub fn create_map_from_string(mut commands: Commands) {
-    let lines: Vec<&str> = MAP_DATA.trim().lines().collect();
+    fn extensions(&self) -> &[&str] {
+        &["map.txt"]
+    }
+}
+
+/// Build a map from an ASCII grid: `#` is a wall, `S` a spawn point, anything else is empty.
+///
+/// Adjacent `#` are merged into long wall segments. The map is centered on the origin.
+pub fn parse_ascii_map(data: &str) -> Map {
+    let lines: Vec<&str> = data.trim().lines().collect();
     let rows = lines.len();
-    let cols = lines[0].len();
-    let tile_size: f32 = 50.0;
+    let cols = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let tile_size = ASCII_TILE_SIZE;
     let offset_x = (cols as f32 * tile_size) / 2.0;
     let offset_y = (rows as f32 * tile_size) / 2.0;
+    let to_world = |x: usize, y: usize| grid_to_world(x, y, tile_size, offset_x, offset_y);
+    let mut map = Map::default();
 
     // Grid to keep track of what we've already built into a long wall
     let mut h_used = vec![vec![false; cols]; rows];
     let mut v_used = vec![vec![false; cols]; rows];
 
+    // lines may have different lengths, pad them with empty tiles
     let grid: Vec<Vec<bool>> = lines
         .iter()
-        .map(|l| l.chars().map(|c| c == '#').collect())
+        .map(|l| {
+            let mut row: Vec<bool> = l.chars().map(|c| c == '#').collect();
+            row.resize(cols, false);
+            row
+        })
         .collect();
 
     // 1. Horizontal Merging
@@ -182,16 +205,10 @@ pub fn create_map_from_string(mut commands: Commands) {
                     h_used[y][x] = true;
                     x += 1;
                 }
-                spawn_wall(
-                    &mut commands,
-                    start_x,
-                    y,
-                    x - 1,
-                    y,
-                    tile_size,
-                    offset_x,
-                    offset_y,
-                );
+                map.walls.push(WallSegment {
+                    start: to_world(start_x, y),
+                    end: to_world(x - 1, y),
+                });
             } else {
                 x += 1;
             }
@@ -209,39 +226,95 @@ pub fn create_map_from_string(mut commands: Commands) {
                     v_used[y][x] = true;
                     y += 1;
                 }
-                spawn_wall(
-                    &mut commands,
-                    x,
-                    start_y,
-                    x,
-                    y - 1,
-                    tile_size,
-                    offset_x,
-                    offset_y,
-                );
+                map.walls.push(WallSegment {
+                    start: to_world(x, start_y),
+                    end: to_world(x, y - 1),
+                });
             } else {
                 y += 1;
             }
         }
     }
+
+    // 3. Spawn points
+    for (y, line) in lines.iter().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            if c == 'S' {
+                map.spawn_points.push(to_world(x, y));
+            }
+        }
+    }
+    map
 }
 
-fn spawn_wall(
-    commands: &mut Commands,
-    x1: usize,
-    y1: usize,
-    x2: usize,
-    y2: usize,
-    size: f32,
-    off_x: f32,
-    off_y: f32,
-) {
-    let start = Vec2::new(x1 as f32 * size - off_x, off_y - y1 as f32 * size);
-    let end = Vec2::new(x2 as f32 * size - off_x, off_y - y2 as f32 * size);
+/// 64 bits FNV-1a hash of a map file.
+///
+/// Not cryptographic: it only detects clients with an outdated or modified map.
+/// CRLF line endings are hashed as LF, so that a checkout converting line endings (git's
+/// `autocrlf` on Windows) still matches the server.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes
+        .iter()
+        .enumerate()
+        .filter(|&(i, &byte)| !(byte == b'\r' && bytes.get(i + 1) == Some(&b'\n')))
+        .fold(OFFSET_BASIS, |hash, (_, byte)| {
+            (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+        })
+}
+
+fn grid_to_world(x: usize, y: usize, size: f32, off_x: f32, off_y: f32) -> Vec2 {
+    Vec2::new(x as f32 * size - off_x, off_y - y as f32 * size)
+}
 
-    commands.spawn(WallBundle::new(start, end, Color::WHITE));
+/// Start loading the selected map, and remove the walls of the previous one.
+fn load_selected_map(
+    mut commands: Commands,
+    selection: Res<MapSelection>,
+    asset_server: Res<AssetServer>,
+    walls: Query<Entity, With<Wall>>,
+) {
+    info!("Loading map {}", selection.0);
+    for wall in walls.iter() {
+        commands.entity(wall).despawn();
+    }
+    commands.remove_resource::<LoadedMap>();
+    commands.insert_resource(PendingMap {
+        path: selection.0.clone(),
+        handle: asset_server.load(selection.0.clone()),
+    });
 }
 
-pub fn create_map_3(mut commands: Commands) {
-    create_map_from_string(commands);
+/// Once the selected map is loaded, spawn its walls and update the spawn points.
+fn spawn_loaded_map(
+    mut commands: Commands,
+    pending: Option<Res<PendingMap>>,
+    maps: Res<Assets<Map>>,
+    asset_server: Res<AssetServer>,
+) {
+    let Some(pending) = pending else {
+        return;
+    };
+    let Some(map) = maps.get(&pending.handle) else {
+        if let Some(bevy::asset::LoadState::Failed(e)) =
+            asset_server.get_load_state(&pending.handle)
+        {
+            error!("Failed to load map {}: {e}", pending.path);
+            commands.trigger(MapLoadFailed {
+                path: pending.path.clone(),
+            });
+            commands.remove_resource::<PendingMap>();
+        }
+        return;
+    };
+    for wall in &map.walls {
+        commands.spawn(WallBundle::new(wall.start, wall.end, Color::WHITE));
+    }
+    commands.insert_resource(SpawnPoints(map.spawn_points.clone()));
+    commands.insert_resource(LoadedMap(MapInfo {
+        path: pending.path.clone(),
+        hash: map.hash,
+    }));
+    commands.remove_resource::<PendingMap>();
 }
@@ -0,0 +1,209 @@
+//! How players move, tuned per [`PlayerClass`] by [`MovementSettings`].
+//!
+//! Clients predict the movement of their player, so they must use the exact same settings as the
+//! server: the server sends its [`MovementSettings`] to clients when they connect, and whenever
+//! they change.
+use avian2d::prelude::LinearVelocity;
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::ActionState;
+use lightyear::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{Dead, PlayerActions};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<MovementSettings>();
+    app.add_systems(PreUpdate, receive_movement_settings);
+    app.add_systems(FixedUpdate, predict_movement);
+}
+
+/// Determines which [`MovementConfig`] a player moves with.
+#[derive(
+    Component, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerClass {
+    #[default]
+    Standard,
+    /// Fast and nimble
+    Scout,
+    /// Slow to start and to stop
+    Heavy,
+}
+
+/// How a player accelerates and stops. Speeds are in units per second.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+#[serde(default, deny_unknown_fields)]
+pub struct MovementConfig {
+    /// Velocity gained per second along each pressed direction.
+    pub acceleration: f32,
+    /// Velocity lost per second along each axis with no direction pressed, or the opposite one.
+    pub friction: f32,
+    /// Players never move faster than this, the server relies on it to detect cheaters.
+    pub max_speed: f32,
+    /// Accelerate as fast diagonally as along an axis, instead of √2 faster.
+    pub normalize_diagonal: bool,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        // 10 units per tick at the default tick rate
+        Self {
+            acceleration: 640.0,
+            friction: 640.0,
+            max_speed: 150.0,
+            normalize_diagonal: false,
+        }
+    }
+}
+
+impl MovementConfig {
+    /// Why the config can't be used, if it can't.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("acceleration", self.acceleration),
+            ("friction", self.friction),
+            ("max_speed", self.max_speed),
+        ] {
+            if !value.is_finite() || value <= 0.0 {
+                return Err(format!("{name} must be a positive number, got {value}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The [`MovementConfig`] of each [`PlayerClass`].
+///
+/// Sent by the server on connection, clients keep the default until they receive it.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq, Reflect)]
+#[serde(default, deny_unknown_fields)]
+pub struct MovementSettings {
+    pub standard: MovementConfig,
+    pub scout: MovementConfig,
+    pub heavy: MovementConfig,
+}
+
+impl Default for MovementSettings {
+    fn default() -> Self {
+        Self {
+            standard: MovementConfig::default(),
+            scout: MovementConfig {
+                acceleration: 960.0,
+                friction: 960.0,
+                max_speed: 200.0,
+                normalize_diagonal: true,
+            },
+            heavy: MovementConfig {
+                acceleration: 320.0,
+                friction: 400.0,
+                max_speed: 110.0,
+                normalize_diagonal: true,
+            },
+        }
+    }
+}
+
+impl MovementSettings {
+    pub fn get(&self, class: PlayerClass) -> &MovementConfig {
+        match class {
+            PlayerClass::Standard => &self.standard,
+            PlayerClass::Scout => &self.scout,
+            PlayerClass::Heavy => &self.heavy,
+        }
+    }
+
+    /// Why the settings can't be used, if they can't.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, config) in [
+            ("standard", &self.standard),
+            ("scout", &self.scout),
+            ("heavy", &self.heavy),
+        ] {
+            config.validate().map_err(|e| format!("{name}.{e}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Only clients receive the settings, the server has no receiver.
+fn receive_movement_settings(
+    mut receivers: Query<&mut MessageReceiver<MovementSettings>>,
+    mut commands: Commands,
+) {
+    for mut receiver in receivers.iter_mut() {
+        if let Some(settings) = receiver.receive().last() {
+            info!("Received movement settings: {settings:?}");
+            commands.insert_resource(settings);
+        }
+    }
+}
+
+/// Clients (and bots) predict the movement of their player, exactly like the server moves it.
+///
+/// The server has no `Predicted` entities, it moves players from the inputs it receives.
+fn predict_movement(
+    time: Res<Time>,
+    settings: Res<MovementSettings>,
+    mut players: Query<
+        (
+            &mut LinearVelocity,
+            &ActionState<PlayerActions>,
+            &PlayerClass,
+        ),
+        (With<Predicted>, Without<Dead>),
+    >,
+) {
+    for (velocity, action_state, class) in players.iter_mut() {
+        shared_movement_behaviour(
+            velocity,
+            action_state,
+            settings.get(*class),
+            time.delta_secs(),
+        );
+    }
+}
+
+// This system defines how we update the player's positions when we receive an input
+pub fn shared_movement_behaviour(
+    mut velocity: Mut<LinearVelocity>,
+    action: &ActionState<PlayerActions>,
+    config: &MovementConfig,
+    delta_secs: f32,
+) {
+    trace!(pressed = ?action.get_pressed(), "shared movement");
+    let mut direction = Vec2::ZERO;
+    if action.pressed(&PlayerActions::Up) {
+        direction.y += 1.0;
+    }
+    if action.pressed(&PlayerActions::Down) {
+        direction.y -= 1.0;
+    }
+    if action.pressed(&PlayerActions::Left) {
+        direction.x -= 1.0;
+    }
+    if action.pressed(&PlayerActions::Right) {
+        direction.x += 1.0;
+    }
+    if config.normalize_diagonal {
+        direction = direction.normalize_or_zero();
+    }
+    let change = direction * config.acceleration * delta_secs;
+    let friction = config.friction * delta_secs;
+
+    fn move_toward_zero(value: f32, step: f32) -> f32 {
+        if value.abs() <= step {
+            0.0
+        } else {
+            value - value.signum() * step
+        }
+    }
+    if change.x == 0f32 || (velocity.x != 0f32 && change.x.signum() != velocity.x.signum()) {
+        velocity.x = move_toward_zero(velocity.x, friction);
+    }
+    if change.y == 0f32 || (velocity.y != 0f32 && change.y.signum() != velocity.y.signum()) {
+        velocity.y = move_toward_zero(velocity.y, friction);
+    }
+    velocity.0 += change;
+    *velocity = LinearVelocity(velocity.clamp_length_max(config.max_speed));
+}
@@ -0,0 +1,77 @@
+//! Projectiles fired by players.
+//!
+//! Projectiles are spawned in `FixedUpdate` both on the server and on the shooter's client:
+//! the client copy is prespawned, and matched with the server entity once it is replicated.
+//! The server is the authority on when projectiles are despawned.
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::ActionState;
+use lightyear::prelude::*;
+
+use crate::game::Frozen;
+use crate::protocol::physics::{PLAYER_SIZE, PROJECTILE_SIZE, PhysicsBundle};
+use crate::protocol::{Dead, PlayerActions, PlayerId, Projectile, Weapon};
+
+/// Number of ticks between two shots of the same player
+pub const FIRE_COOLDOWN_TICKS: u16 = 16;
+/// Number of ticks a projectile lives if it doesn't hit anything
+pub const PROJECTILE_LIFETIME_TICKS: u16 = 128;
+pub const PROJECTILE_SPEED: f32 = 400.0;
+pub const PROJECTILE_DAMAGE: u16 = 25;
+
+/// Number of ticks before the projectile is despawned by the server.
+#[derive(Component, Debug)]
+pub struct ProjectileLifetime(pub u16);
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(FixedUpdate, fire_projectiles);
+}
+
+/// Spawn a projectile for each player pressing Fire whose weapon is ready.
+///
+/// Runs on the server for every player, and on clients for the predicted player only.
+pub fn fire_projectiles(
+    mut commands: Commands,
+    mut players: Query<
+        (
+            &PlayerId,
+            &Position,
+            &LinearVelocity,
+            &mut Weapon,
+            &ActionState<PlayerActions>,
+        ),
+        (
+            Or<(With<Predicted>, With<Replicate>)>,
+            Without<Dead>,
+            Without<Frozen>,
+        ),
+    >,
+) {
+    for (player_id, position, velocity, mut weapon, action) in players.iter_mut() {
+        if weapon.cooldown_ticks > 0 {
+            weapon.cooldown_ticks -= 1;
+            continue;
+        }
+        if !action.pressed(&PlayerActions::Fire) {
+            continue;
+        }
+        weapon.cooldown_ticks = FIRE_COOLDOWN_TICKS;
+
+        // Players have no aim, shoot where they are going.
+        let direction = velocity.0.try_normalize().unwrap_or(Vec2::Y);
+        commands.spawn((
+            Projectile { owner: player_id.0 },
+            ProjectileLifetime(PROJECTILE_LIFETIME_TICKS),
+            // spawn outside of the shooter's collider
+            Position(position.0 + direction * (PLAYER_SIZE + PROJECTILE_SIZE + 1.0)),
+            Rotation::default(),
+            LinearVelocity(direction * PROJECTILE_SPEED),
+            PhysicsBundle::projectile(),
+            Sensor,
+            CollisionEventsEnabled,
+            // the salt distinguishes projectiles fired on the same tick by different players
+            PreSpawned::default_with_salt(player_id.0.to_bits()),
+            Name::from("Projectile"),
+        ));
+    }
+}
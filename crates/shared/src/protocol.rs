@@ -9,6 +9,8 @@ use lightyear::prelude::input::leafwing;
 use lightyear::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use crate::game::movement::{MovementSettings, PlayerClass};
+
 // Components
 
 #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -21,6 +23,35 @@ pub struct ColorComponent(pub Color);
 // Marker component
 pub struct CircleMarker;
 
+/// A projectile fired by a player.
+#[derive(Component, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Projectile {
+    pub owner: PeerId,
+}
+
+#[derive(Component, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Health {
+    pub current: u16,
+    pub max: u16,
+}
+
+impl Health {
+    pub fn new(max: u16) -> Self {
+        Self { current: max, max }
+    }
+}
+
+/// Marker component for a dead player, waiting to respawn.
+#[derive(Component, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Dead;
+
+/// Allows a player to fire projectiles.
+#[derive(Component, Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+pub struct Weapon {
+    /// Number of ticks before the weapon can fire again
+    pub cooldown_ticks: u16,
+}
+
 // Connection
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -42,6 +73,17 @@ pub struct ChannelPreGame;
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct JoinGame;
 
+/// Sent by the server on connection and whenever the map changes.
+///
+/// Clients load the map at `path`, and only join the game if its hash matches.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MapInfo {
+    /// Asset path of the map, eg: `maps/labyrinth.map.txt`.
+    pub path: String,
+    /// [`content_hash`](crate::game::map::content_hash) of the map file.
+    pub hash: u64,
+}
+
 // Inputs
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Hash, Reflect, Actionlike)]
@@ -63,10 +105,14 @@ impl Plugin for ProtocolPlugin {
             mode: ChannelMode::OrderedReliable(ReliableSettings::default()),
             ..default()
         })
-        .add_direction(NetworkDirection::ClientToServer);
+        .add_direction(NetworkDirection::Bidirectional);
         // messages
         app.register_message::<JoinGame>()
             .add_direction(NetworkDirection::ClientToServer);
+        app.register_message::<MapInfo>()
+            .add_direction(NetworkDirection::ServerToClient);
+        app.register_message::<MovementSettings>()
+            .add_direction(NetworkDirection::ServerToClient);
         // inputs
 
         app.add_plugins(leafwing::InputPlugin::<PlayerActions> {
@@ -77,10 +123,19 @@ impl Plugin for ProtocolPlugin {
         });
         // components
         app.register_component::<PlayerId>();
+        app.register_component::<PlayerClass>();
 
         app.register_component::<ColorComponent>();
         app.register_component::<CircleMarker>();
 
+        // Projectiles are prespawned on the shooter's client, so they need to be predicted.
+        app.register_component::<Projectile>().add_prediction();
+        app.register_component::<Weapon>().add_prediction();
+
+        // Predicted so that the local player stops moving and shooting while dead.
+        app.register_component::<Health>().add_prediction();
+        app.register_component::<Dead>().add_prediction();
+
         // Fully replicated, but not visual, so no need for lerp/corrections:
         app.register_component::<LinearVelocity>()
             .add_prediction()
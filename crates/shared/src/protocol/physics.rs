@@ -2,6 +2,7 @@ use avian2d::prelude::*;
 use bevy::prelude::*;
 
 pub const PLAYER_SIZE: f32 = 10f32;
+pub const PROJECTILE_SIZE: f32 = 3f32;
 
 #[derive(Bundle)]
 pub struct PhysicsBundle {
@@ -24,4 +25,16 @@ impl PhysicsBundle {
             mass,
         }
     }
+
+    pub fn projectile() -> Self {
+        let collider = Collider::circle(PROJECTILE_SIZE);
+        let mass = MassPropertiesBundle::from_shape(&collider, 0.01f32);
+        Self {
+            collider,
+            collider_density: ColliderDensity(0.01),
+            rigid_body: RigidBody::Dynamic,
+            restitution: Restitution::new(0.0),
+            mass,
+        }
+    }
 }
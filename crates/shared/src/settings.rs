@@ -8,6 +8,10 @@ pub const CLIENT_PORT: u16 = 0;
 pub const SERVER_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), SERVER_PORT);
 pub const SHARED_SETTINGS: SharedSettings = SharedSettings { protocol_id: 0 };
 
+/// Assets folder of native binaries, relative to their crate directory.
+/// On the web, the folder is copied next to the wasm by trunk and served at the default `assets`.
+pub const ASSETS_PATH: &str = "../../assets";
+
 pub const SEND_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Copy, Clone, Debug)]
@@ -0,0 +1,91 @@
+//! Uniform grid spatial index, to find the entities around a position without scanning all of them.
+//!
+//! The grid is sparse: only cells containing entities are allocated, so the world doesn't need bounds.
+use bevy::ecs::entity::EntityHashMap;
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+
+#[derive(Debug, Clone)]
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<Entity>>,
+    positions: EntityHashMap<Vec2>,
+}
+
+impl SpatialGrid {
+    /// Queries are the fastest when `cell_size` is close to the radius being queried.
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "cell size must be positive");
+        Self {
+            cell_size,
+            cells: HashMap::default(),
+            positions: EntityHashMap::default(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn position(&self, entity: Entity) -> Option<Vec2> {
+        self.positions.get(&entity).copied()
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    /// Adds `entity` at `position`, or moves it if it is already in the grid.
+    pub fn insert(&mut self, entity: Entity, position: Vec2) {
+        let new_cell = self.cell(position);
+        if let Some(previous) = self.positions.insert(entity, position) {
+            let old_cell = self.cell(previous);
+            if old_cell == new_cell {
+                return;
+            }
+            self.remove_from_cell(old_cell, entity);
+        }
+        self.cells.entry(new_cell).or_default().push(entity);
+    }
+
+    /// Removes `entity` from the grid, returns false if it wasn't in it.
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        let Some(position) = self.positions.remove(&entity) else {
+            return false;
+        };
+        self.remove_from_cell(self.cell(position), entity);
+        true
+    }
+
+    fn remove_from_cell(&mut self, cell: IVec2, entity: Entity) {
+        if let Some(entities) = self.cells.get_mut(&cell) {
+            entities.retain(|e| *e != entity);
+            if entities.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    /// Iterates over the entities strictly closer than `radius` to `center`, with their position.
+    pub fn within_radius(
+        &self,
+        center: Vec2,
+        radius: f32,
+    ) -> impl Iterator<Item = (Entity, Vec2)> + '_ {
+        let min = self.cell(center - Vec2::splat(radius));
+        let max = self.cell(center + Vec2::splat(radius));
+        let radius_squared = radius * radius;
+        (min.x..=max.x)
+            .flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter_map(move |entity| {
+                let position = self.positions[entity];
+                (position.distance_squared(center) < radius_squared).then_some((*entity, position))
+            })
+    }
+}